@@ -0,0 +1,457 @@
+//! Maps keys and gamepad input onto the actions the game responds to.
+//!
+//! Systems read a `ButtonInput<Action>` instead of the raw input, so every
+//! action works with whatever the player bound to it in the config. Each
+//! player has their own bindings, and `ButtonInput<PlayerAction>` tells them
+//! apart for the actions that steer a particular snake.
+
+use crate::{config::GameConfig, input::Player};
+use bevy::{
+    ecs::system::SystemParam,
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads},
+        InputSystem,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+/// How far a stick has to be pushed along an axis to count as pressed.
+const STICK_THRESHOLD: f32 = 0.5;
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, update_actions.after(InputSystem))
+            .init_resource::<ButtonInput<Action>>()
+            .init_resource::<ButtonInput<PlayerAction>>();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Pause,
+    ToggleAutopilot,
+    ToggleHints,
+    SwitchController,
+    Restart,
+    ToggleDebugOverlay,
+    /// Makes one move of a paused replay.
+    StepPlayback,
+    SlowerPlayback,
+    FasterPlayback,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Pause,
+        Action::ToggleAutopilot,
+        Action::ToggleHints,
+        Action::SwitchController,
+        Action::Restart,
+        Action::ToggleDebugOverlay,
+        Action::StepPlayback,
+        Action::SlowerPlayback,
+        Action::FasterPlayback,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::Pause => "Pause",
+            Action::ToggleAutopilot => "Autopilot",
+            Action::ToggleHints => "Hints",
+            Action::SwitchController => "Switch Bot",
+            Action::Restart => "Restart",
+            Action::ToggleDebugOverlay => "Debug",
+            Action::StepPlayback => "Step",
+            Action::SlowerPlayback => "Slower",
+            Action::FasterPlayback => "Faster",
+        }
+    }
+}
+
+/// An action done through one player's bindings. `player` counts from 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PlayerAction {
+    pub player: usize,
+    pub action: Action,
+}
+
+/// A key, gamepad button or stick direction an action can be bound to.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    GamepadButton(GamepadButtonType),
+    /// A stick pushed along `axis`, towards the positive end if `positive`.
+    GamepadAxis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl Binding {
+    pub fn is_gamepad(self) -> bool {
+        !matches!(self, Binding::Key(_))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Binding::Key(KeyCode::BracketLeft) => write!(f, "["),
+            Binding::Key(KeyCode::BracketRight) => write!(f, "]"),
+            Binding::Key(KeyCode::Period) => write!(f, "."),
+            Binding::Key(key) => {
+                let name = format!("{key:?}");
+                let name = name.strip_prefix("Key").unwrap_or(&name);
+                let name = name.strip_prefix("Digit").unwrap_or(name);
+                write!(f, "{name}")
+            }
+            Binding::GamepadButton(button) => match button {
+                GamepadButtonType::DPadUp => write!(f, "D-pad Up"),
+                GamepadButtonType::DPadDown => write!(f, "D-pad Down"),
+                GamepadButtonType::DPadLeft => write!(f, "D-pad Left"),
+                GamepadButtonType::DPadRight => write!(f, "D-pad Right"),
+                button => write!(f, "Pad {button:?}"),
+            },
+            Binding::GamepadAxis { axis, positive } => match (axis, positive) {
+                (GamepadAxisType::LeftStickX, false) => write!(f, "Left Stick Left"),
+                (GamepadAxisType::LeftStickX, true) => write!(f, "Left Stick Right"),
+                (GamepadAxisType::LeftStickY, false) => write!(f, "Left Stick Down"),
+                (GamepadAxisType::LeftStickY, true) => write!(f, "Left Stick Up"),
+                (GamepadAxisType::RightStickX, false) => write!(f, "Right Stick Left"),
+                (GamepadAxisType::RightStickX, true) => write!(f, "Right Stick Right"),
+                (GamepadAxisType::RightStickY, false) => write!(f, "Right Stick Down"),
+                (GamepadAxisType::RightStickY, true) => write!(f, "Right Stick Up"),
+                (axis, positive) => write!(f, "{axis:?} {}", if positive { "+" } else { "-" }),
+            },
+        }
+    }
+}
+
+/// What each action is bound to for one player. Stored in the config file.
+///
+/// Gamepad bindings only respond to the player's own gamepad, so every player
+/// can use the same ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+impl Bindings {
+    /// The bindings `player` starts out with, counting from 0. Each of the
+    /// first four players steers with their own keys, and only the first
+    /// gets keys for the other actions.
+    pub fn for_player(player: usize) -> Bindings {
+        use Binding::{GamepadAxis as Axis, GamepadButton as Button, Key};
+        use GamepadAxisType::{LeftStickX, LeftStickY};
+
+        let keys = match player {
+            0 => Some([KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD]),
+            1 => Some([
+                KeyCode::ArrowUp,
+                KeyCode::ArrowDown,
+                KeyCode::ArrowLeft,
+                KeyCode::ArrowRight,
+            ]),
+            2 => Some([KeyCode::KeyI, KeyCode::KeyK, KeyCode::KeyJ, KeyCode::KeyL]),
+            3 => Some([
+                KeyCode::Numpad8,
+                KeyCode::Numpad5,
+                KeyCode::Numpad4,
+                KeyCode::Numpad6,
+            ]),
+            _ => None,
+        };
+        let key = |i: usize| keys.map(|keys| Key(keys[i]));
+        let first_player_key = |key| (player == 0).then_some(Key(key));
+
+        let bindings = [
+            (
+                Action::Up,
+                vec![
+                    key(0),
+                    Some(Button(GamepadButtonType::DPadUp)),
+                    Some(Axis {
+                        axis: LeftStickY,
+                        positive: true,
+                    }),
+                ],
+            ),
+            (
+                Action::Down,
+                vec![
+                    key(1),
+                    Some(Button(GamepadButtonType::DPadDown)),
+                    Some(Axis {
+                        axis: LeftStickY,
+                        positive: false,
+                    }),
+                ],
+            ),
+            (
+                Action::Left,
+                vec![
+                    key(2),
+                    Some(Button(GamepadButtonType::DPadLeft)),
+                    Some(Axis {
+                        axis: LeftStickX,
+                        positive: false,
+                    }),
+                ],
+            ),
+            (
+                Action::Right,
+                vec![
+                    key(3),
+                    Some(Button(GamepadButtonType::DPadRight)),
+                    Some(Axis {
+                        axis: LeftStickX,
+                        positive: true,
+                    }),
+                ],
+            ),
+            (
+                Action::Pause,
+                vec![
+                    first_player_key(KeyCode::Space),
+                    Some(Button(GamepadButtonType::Start)),
+                ],
+            ),
+            (
+                Action::ToggleAutopilot,
+                vec![
+                    first_player_key(KeyCode::KeyP),
+                    Some(Button(GamepadButtonType::Select)),
+                ],
+            ),
+            (
+                Action::ToggleHints,
+                vec![
+                    first_player_key(KeyCode::KeyH),
+                    Some(Button(GamepadButtonType::West)),
+                ],
+            ),
+            (
+                Action::SwitchController,
+                vec![
+                    first_player_key(KeyCode::KeyO),
+                    Some(Button(GamepadButtonType::North)),
+                ],
+            ),
+            (
+                Action::Restart,
+                vec![
+                    first_player_key(KeyCode::KeyR),
+                    Some(Button(GamepadButtonType::South)),
+                ],
+            ),
+            (
+                Action::ToggleDebugOverlay,
+                vec![first_player_key(KeyCode::F3)],
+            ),
+            (
+                Action::StepPlayback,
+                vec![
+                    first_player_key(KeyCode::Period),
+                    Some(Button(GamepadButtonType::East)),
+                ],
+            ),
+            (
+                Action::SlowerPlayback,
+                vec![
+                    first_player_key(KeyCode::BracketLeft),
+                    Some(Button(GamepadButtonType::LeftTrigger)),
+                ],
+            ),
+            (
+                Action::FasterPlayback,
+                vec![
+                    first_player_key(KeyCode::BracketRight),
+                    Some(Button(GamepadButtonType::RightTrigger)),
+                ],
+            ),
+        ];
+        Bindings(
+            bindings
+                .into_iter()
+                .map(|(action, bindings)| (action, bindings.into_iter().flatten().collect()))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Binds `binding` to `action` in place of whatever the action was bound
+    /// to on the same device, taking it away from any other action.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.unbind(binding);
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|b| b.is_gamepad() != binding.is_gamepad());
+        bindings.push(binding);
+    }
+
+    /// Gives every action `defaults` has but these bindings don't mention the
+    /// default bindings for it, leaving out any already bound to something
+    /// else. Lets configs saved before an action existed pick it up.
+    pub fn add_missing(&mut self, defaults: &Bindings) {
+        for (&action, bindings) in &defaults.0 {
+            if self.0.contains_key(&action) {
+                continue;
+            }
+            let free = bindings
+                .iter()
+                .copied()
+                .filter(|binding| !self.0.values().flatten().any(|b| b == binding))
+                .collect();
+            self.0.insert(action, free);
+        }
+    }
+
+    /// Takes `binding` away from whichever action it's bound to.
+    pub fn unbind(&mut self, binding: Binding) {
+        for bindings in self.0.values_mut() {
+            bindings.retain(|&b| b != binding);
+        }
+    }
+
+    /// The first key bound to `action`, for showing it in the UI.
+    pub fn key(&self, action: Action) -> Option<Binding> {
+        self.get(action).iter().copied().find(|b| !b.is_gamepad())
+    }
+}
+
+/// The raw keyboard and gamepad input that bindings are read from.
+#[derive(SystemParam)]
+pub struct RawInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+impl RawInput<'_> {
+    /// Whether `binding` is held down on the keyboard or any gamepad.
+    pub fn pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            _ => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.gamepad_pressed(gamepad, binding)),
+        }
+    }
+
+    /// Whether `binding` is held down on the keyboard or on `player`'s
+    /// gamepad. Players get the gamepads in the order they were connected.
+    pub fn pressed_by(&self, binding: Binding, player: usize) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            _ => {
+                let mut gamepads: Vec<_> = self.gamepads.iter().collect();
+                gamepads.sort_by_key(|gamepad| gamepad.id);
+                gamepads
+                    .get(player)
+                    .is_some_and(|&gamepad| self.gamepad_pressed(gamepad, binding))
+            }
+        }
+    }
+
+    fn gamepad_pressed(&self, gamepad: Gamepad, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(_) => false,
+            Binding::GamepadButton(button) => {
+                self.buttons.pressed(GamepadButton::new(gamepad, button))
+            }
+            Binding::GamepadAxis { axis, positive } => {
+                let value = self.axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.);
+                if positive {
+                    value > STICK_THRESHOLD
+                } else {
+                    value < -STICK_THRESHOLD
+                }
+            }
+        }
+    }
+
+    /// A key pressed this frame, other than Escape.
+    pub fn new_key(&self) -> Option<Binding> {
+        self.keys
+            .get_just_pressed()
+            .find(|&&key| key != KeyCode::Escape)
+            .map(|&key| Binding::Key(key))
+    }
+
+    /// A gamepad button pressed this frame, or a stick that is pushed over.
+    pub fn new_gamepad_input(&self) -> Option<Binding> {
+        if let Some(button) = self.buttons.get_just_pressed().next() {
+            return Some(Binding::GamepadButton(button.button_type));
+        }
+
+        let sticks = [
+            GamepadAxisType::LeftStickX,
+            GamepadAxisType::LeftStickY,
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+        ];
+        sticks
+            .into_iter()
+            .flat_map(|axis| [true, false].map(|positive| Binding::GamepadAxis { axis, positive }))
+            .find(|&binding| self.pressed(binding))
+    }
+}
+
+fn update_actions(
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut player_actions: ResMut<ButtonInput<PlayerAction>>,
+    config: Res<GameConfig>,
+    input: RawInput,
+    player_q: Query<&Player>,
+) {
+    actions.clear();
+    player_actions.clear();
+    // With only one snake steered from this machine, everyone's bindings
+    // steer it
+    let shared = player_q.iter().count() <= 1;
+    for action in Action::ALL {
+        let mut held = vec![false; config.controls.len()];
+        for (player, controls) in config.controls.iter().enumerate() {
+            let steers = if shared { 0 } else { player };
+            held[steers] |= controls
+                .bindings
+                .get(action)
+                .iter()
+                .any(|&b| input.pressed_by(b, player));
+        }
+
+        for (player, held) in held.into_iter().enumerate() {
+            let player_action = PlayerAction { player, action };
+            if held {
+                player_actions.press(player_action);
+            } else {
+                player_actions.release(player_action);
+            }
+        }
+        if player_actions
+            .any_pressed((0..config.controls.len()).map(|player| PlayerAction { player, action }))
+        {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+    }
+}
@@ -1,277 +1,444 @@
 use crate::{
-    constants::*,
-    food::Food,
+    actions::Action,
+    config::GameConfig,
+    controller::{ControllerEntry, ControllerRegistry, Plan, SnakeController},
+    external::{ExternalController, EXTERNAL_CONTROLLER},
+    game::{Direction, GameState},
     input::NextDirection,
-    snake::{SnakeBody, SnakeMoveEvent},
-};
-use bevy::prelude::*;
-use std::{
-    cmp::Ordering,
-    collections::{BinaryHeap, HashMap, HashSet},
+    netplay::online,
+    replay::Playback,
+    rival::RivalController,
+    snake::{Game, SetupSnakeSet, SnakeId, SnakeMoveEvent, SnakeMoveSet},
+    state::AppState,
 };
+use bevy::{prelude::*, utils::Duration};
 
 pub struct AutopilotPlugin;
 
 impl Plugin for AutopilotPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_autopilot_button)
+        let registry = ControllerRegistry::default();
+        let controller = ActiveController::new(registry.first());
+
+        app.add_systems(Startup, (register_bot, setup_autopilot_buttons))
+            .add_systems(
+                OnEnter(AppState::Playing),
+                (reset_autopilot, start_controller.after(SetupSnakeSet)),
+            )
             .add_systems(
                 Update,
                 (
-                    autopilot_snake.in_set(AutopilotSet),
+                    (
+                        // It would be steering from where the snake was a few
+                        // moves ago, once the input delay held its moves back
+                        autopilot_snake.run_if(not(online)),
+                        steer_rivals,
+                    )
+                        .in_set(AutopilotSet)
+                        .run_if(not(resource_exists::<Playback>)),
                     handle_button_click,
-                    toggle_autopilot,
-                    update_autopilot_button,
+                    (toggle_autopilot, toggle_hints, switch_controller_on_key)
+                        .run_if(in_state(AppState::Playing)),
+                    (
+                        (prepare_autopilot, update_hint).run_if(not(online)),
+                        draw_hint,
+                    )
+                        .chain()
+                        .after(SnakeMoveSet)
+                        .run_if(in_state(AppState::Playing))
+                        .run_if(not(resource_exists::<Playback>)),
+                    update_autopilot_buttons,
                 ),
             )
-            .insert_resource(Autopilot(false));
+            .insert_resource(Autopilot(false))
+            .insert_resource(Hints(false))
+            .insert_resource(registry)
+            .insert_resource(controller);
     }
 }
 
 #[derive(Resource)]
 pub struct Autopilot(pub bool);
 
-#[derive(Component)]
-struct AutopilotButtonText;
+/// Whether the move the controller would make is shown while the player
+/// steers. Has no effect while the autopilot is on.
+#[derive(Resource)]
+pub struct Hints(pub bool);
 
-#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
-pub struct AutopilotSet;
+/// The controller that steers the snake while the autopilot is on, and that
+/// suggests moves while hints are on.
+#[derive(Resource)]
+pub struct ActiveController {
+    name: &'static str,
+    label: &'static str,
+    controller: Box<dyn SnakeController>,
+    /// The move the controller would make next, when it's only giving hints.
+    suggestion: Option<Direction>,
+}
 
-#[derive(Copy, Clone)]
-struct Node {
-    position: IVec2,
-    previous: Option<IVec2>,
-    f_score: i32,
-    g_score: i32,
+/// Steers the rival snake whose entity it's on.
+#[derive(Component)]
+pub struct Rival(pub RivalController);
+
+#[derive(Component, Copy, Clone)]
+enum AutopilotButton {
+    Toggle,
+    SwitchController,
+    Hints,
 }
 
-impl Eq for Node {}
+#[derive(Component)]
+struct AutopilotButtonText(AutopilotButton);
 
-impl Ord for Node {
-    fn cmp(&self, other: &Node) -> Ordering {
-        other
-            .f_score
-            .cmp(&self.f_score)
-            .then(other.position.x.cmp(&self.position.x))
-            .then(other.position.y.cmp(&self.position.y))
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AutopilotSet;
+
+impl ActiveController {
+    fn new(entry: &ControllerEntry) -> Self {
+        ActiveController {
+            name: entry.name,
+            label: entry.label,
+            controller: entry.create(),
+            suggestion: None,
+        }
     }
-}
 
-impl PartialEq for Node {
-    fn eq(&self, other: &Node) -> bool {
-        self.position == other.position
+    pub fn label(&self) -> &'static str {
+        self.label
     }
-}
 
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
-        Some(self.cmp(other))
+    /// The reasoning behind the controller's last move, if it keeps track.
+    pub fn plan(&self) -> Option<&Plan> {
+        self.controller.plan()
     }
 }
 
-const CARDINAL_DIRECTIONS: [Dir2; 4] = [Dir2::X, Dir2::NEG_X, Dir2::Y, Dir2::NEG_Y];
-
 fn autopilot_snake(
     autopilot: Res<Autopilot>,
-    body_q: Query<&Transform, Without<Food>>,
-    food_q: Query<&Transform, With<Food>>,
-    body: Res<SnakeBody>,
+    game: Res<Game>,
+    mut active: ResMut<ActiveController>,
     mut ev_move: EventReader<SnakeMoveEvent>,
-    mut next_direction: ResMut<NextDirection>,
+    mut snake_q: Query<(&SnakeId, &mut NextDirection)>,
 ) {
-    if !autopilot.0 || ev_move.is_empty() {
+    if ev_move.is_empty() {
         return;
     }
 
     ev_move.clear();
 
-    let head_transform = body_q.get(body.0[0]).unwrap();
-    let food_transform = food_q.single();
-
-    let start = head_transform.translation.xy().as_ivec2();
-    let end = food_transform.translation.xy().as_ivec2();
-
-    let body_positions: Vec<_> = body_q
-        .iter()
-        .map(|t| t.translation.xy().as_ivec2())
-        .collect();
+    // Only the first snake has an autopilot, and only while it's alive
+    if !autopilot.0 || !game.0.snakes()[0].is_alive() {
+        return;
+    }
 
-    if let Some(&next_pos) = find_path(start, end, &body_positions).get(1) {
-        next_direction.0 = Dir2::new_unchecked((next_pos - start).as_vec2());
-    } else if let Some(direction) = survival_mode(start, &body_positions) {
-        next_direction.0 = direction;
+    if let Some(direction) = active.controller.next_direction(&game.0) {
+        if let Some((_, mut next_direction)) = snake_q.iter_mut().find(|(id, _)| id.0 == 0) {
+            next_direction.0 = direction;
+        }
     }
 }
 
-fn survival_mode(start: IVec2, body_positions: &[IVec2]) -> Option<Dir2> {
-    let mut largest_area = 0;
-    let mut best_direction = None;
+/// Shows the controller the board the snake's next move will be made from as
+/// soon as it's known, so it has until the move is due to think about it.
+fn prepare_autopilot(
+    autopilot: Res<Autopilot>,
+    game: Res<Game>,
+    mut active: ResMut<ActiveController>,
+    mut prepared: Local<&'static str>,
+) {
+    // Hints change the controller resource too, so a new controller is
+    // told apart by its name
+    if !game.is_changed() && !autopilot.is_changed() && *prepared == active.name {
+        return;
+    }
+    *prepared = active.name;
+    if autopilot.0 && game.0.snakes()[0].is_alive() {
+        active.controller.prepare(&game.0);
+    }
+}
 
-    for direction in CARDINAL_DIRECTIONS {
-        let next_pos = start + direction.as_ivec2();
+/// Works out the move to suggest once the snake has moved, so the hint shows
+/// the way from the cell the snake is on for the whole of the next move.
+fn update_hint(
+    autopilot: Res<Autopilot>,
+    hints: Res<Hints>,
+    game: Res<Game>,
+    mut active: ResMut<ActiveController>,
+) {
+    if !game.is_changed() && !hints.is_changed() && !autopilot.is_changed() && !active.is_changed()
+    {
+        return;
+    }
 
-        if is_valid_move(next_pos, &body_positions) {
-            let area = flood_fill(next_pos, &body_positions);
-            if area > largest_area {
-                largest_area = area;
-                best_direction = Some(direction);
-            }
-        }
+    active.suggestion = None;
+    // Leave the steering to the player, and only while their snake is alive
+    if autopilot.0 || !hints.0 || !game.0.snakes()[0].is_alive() {
+        return;
     }
-    best_direction
+    let suggestion = suggest_move(active.controller.as_mut(), &game.0);
+    active.suggestion = Some(suggestion);
 }
 
-fn find_path(start: IVec2, end: IVec2, body_positions: &[IVec2]) -> Vec<IVec2> {
-    let mut cells = HashMap::new();
-    let mut open_list = BinaryHeap::new();
+/// The move `controller` would make next from where the snake is now, going
+/// straight on if it has nothing better.
+fn suggest_move(controller: &mut dyn SnakeController, game: &GameState) -> Direction {
+    controller.next_direction(game).unwrap_or(game.direction())
+}
 
-    // Start with the end position
-    let node = Node {
-        position: end,
-        previous: None,
-        f_score: 0,
-        g_score: 0,
-    };
-    cells.insert(end, node);
-    open_list.push(node);
+fn steer_rivals(
+    game: Res<Game>,
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut rival_q: Query<(&SnakeId, &mut Rival, &mut NextDirection)>,
+) {
+    if ev_move.is_empty() {
+        return;
+    }
+    ev_move.clear();
 
-    while let Some(Node {
-        position: mut current,
-        ..
-    }) = open_list.pop()
-    {
-        // Reached the start, reconstruct path
-        if current == start {
-            let mut path = vec![current];
-            while let Some(&Node {
-                previous: Some(previous),
-                ..
-            }) = cells.get(&current)
-            {
-                path.push(previous);
-                current = previous;
-            }
-            return path;
+    for (&SnakeId(id), mut rival, mut next_direction) in &mut rival_q {
+        if !game.0.snakes()[id].is_alive() {
+            continue;
         }
-
-        let neighbors = CARDINAL_DIRECTIONS
-            .iter()
-            .map(|direction| current + direction.as_ivec2());
-
-        for neighbor in neighbors {
-            if body_positions.contains(&neighbor) && neighbor != start
-                || neighbor.x < 0
-                || neighbor.y < 0
-                || neighbor.x >= GRID_WIDTH as i32
-                || neighbor.y >= GRID_HEIGHT as i32
-            {
-                continue;
-            }
-
-            let g_score = cells[&current].g_score + 1;
-            let h_score = manhattan_distance(neighbor, start);
-            let f_score = g_score + h_score;
-
-            if !cells.contains_key(&neighbor) || g_score < cells[&neighbor].g_score {
-                let node = Node {
-                    position: neighbor,
-                    previous: Some(current),
-                    f_score,
-                    g_score,
-                };
-                cells.insert(neighbor, node);
-                open_list.push(node);
-            }
+        if let Some(direction) = rival.0.next_direction(&game.0.seen_by(id)) {
+            next_direction.0 = direction;
         }
     }
-    vec![]
 }
 
-fn flood_fill(start: IVec2, body_positions: &[IVec2]) -> usize {
-    let mut stack = vec![start];
-    let mut visited = HashSet::new();
-    let mut area = 0;
+/// Points out the suggested move from the head, followed by the rest of the
+/// path the controller has in mind.
+fn draw_hint(
+    mut gizmos: Gizmos,
+    autopilot: Res<Autopilot>,
+    hints: Res<Hints>,
+    active: Res<ActiveController>,
+    game: Res<Game>,
+) {
+    let Some(direction) = active.suggestion else {
+        return;
+    };
+    if autopilot.0 || !hints.0 {
+        return;
+    }
 
-    while let Some(pos) = stack.pop() {
-        if !visited.contains(&pos) && is_valid_move(pos, &body_positions) {
-            visited.insert(pos);
-            area += 1;
+    let head = game.0.head().as_vec2();
+    let color = Srgba::rgb(0.2, 0.8, 1.);
+    gizmos.arrow_2d(head, head + direction.as_ivec2().as_vec2() * 0.8, color);
+
+    // The arrow already covers the first step
+    let path = active.plan().map_or(&[][..], |plan| &plan.path[..]);
+    draw_path(
+        &mut gizmos,
+        path.get(1..).unwrap_or_default(),
+        color.with_alpha(0.5),
+    );
+}
 
-            for direction in CARDINAL_DIRECTIONS {
-                stack.push(pos + direction.as_ivec2());
-            }
+/// Draws a line along `path`, leaving out the jumps across the board where it
+/// wraps around.
+pub fn draw_path(gizmos: &mut Gizmos, path: &[IVec2], color: impl Into<Color> + Copy) {
+    for step in path.windows(2) {
+        if (step[1] - step[0]).abs().element_sum() == 1 {
+            gizmos.line_2d(step[0].as_vec2(), step[1].as_vec2(), color);
         }
     }
-
-    area
 }
 
 fn handle_button_click(
     mut autopilot: ResMut<Autopilot>,
-    mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>)>,
+    mut hints: ResMut<Hints>,
+    mut active: ResMut<ActiveController>,
+    game: Option<Res<Game>>,
+    registry: Res<ControllerRegistry>,
+    interaction_query: Query<(&Interaction, &AutopilotButton), Changed<Interaction>>,
 ) {
-    for &interaction in &mut interaction_query {
+    for (&interaction, &button) in &interaction_query {
         if interaction == Interaction::Pressed {
-            autopilot.0 = !autopilot.0;
+            match button {
+                AutopilotButton::Toggle => autopilot.0 = !autopilot.0,
+                AutopilotButton::Hints => hints.0 = !hints.0,
+                AutopilotButton::SwitchController => {
+                    switch_controller(&mut active, &registry, game.as_deref())
+                }
+            }
         }
     }
 }
 
-fn is_valid_move(pos: IVec2, body_positions: &[IVec2]) -> bool {
-    pos.x >= 0
-        && pos.x < GRID_WIDTH as i32
-        && pos.y >= 0
-        && pos.y < GRID_HEIGHT as i32
-        && !body_positions.contains(&pos)
+/// Hands the snake over to the next controller in the registry.
+fn switch_controller(
+    active: &mut ActiveController,
+    registry: &ControllerRegistry,
+    game: Option<&Game>,
+) {
+    *active = ActiveController::new(registry.step(active.name, 1));
+    if let Some(game) = game {
+        active.controller.reset(&game.0);
+    }
+}
+
+fn reset_autopilot(
+    mut autopilot: ResMut<Autopilot>,
+    mut hints: ResMut<Hints>,
+    config: Res<GameConfig>,
+) {
+    autopilot.0 = config.autopilot;
+    hints.0 = config.hints;
 }
 
-fn manhattan_distance(a: IVec2, b: IVec2) -> i32 {
-    (a.x - b.x).abs() + (a.y - b.y).abs()
+/// Offers the external bot named in the config alongside the built-in
+/// controllers.
+fn register_bot(mut registry: ResMut<ControllerRegistry>, config: Res<GameConfig>) {
+    let Some(command) = config.bot.clone() else {
+        return;
+    };
+    let timeout = Duration::from_millis(config.bot_timeout);
+    registry.register(EXTERNAL_CONTROLLER, "External", move || {
+        Box::new(ExternalController::new(&command, timeout))
+    });
 }
 
-fn setup_autopilot_button(mut cmd: Commands) {
-    cmd.spawn(ButtonBundle {
+fn start_controller(
+    mut active: ResMut<ActiveController>,
+    config: Res<GameConfig>,
+    game: Res<Game>,
+    registry: Res<ControllerRegistry>,
+) {
+    let entry = registry.get(&config.autopilot_controller);
+    if entry.name != config.autopilot_controller {
+        warn!(
+            "Unknown autopilot controller `{}`, using `{}` instead",
+            config.autopilot_controller, entry.name
+        );
+    }
+
+    *active = ActiveController::new(entry);
+    active.controller.reset(&game.0);
+}
+
+fn setup_autopilot_buttons(mut cmd: Commands) {
+    cmd.spawn(NodeBundle {
         style: Style {
-            flex_basis: Val::Px(150.),
-            flex_shrink: 0.,
             position_type: PositionType::Absolute,
             left: Val::Px(10.),
             bottom: Val::Px(10.),
-            padding: UiRect::all(Val::Px(5.)),
+            column_gap: Val::Px(10.),
             ..default()
         },
-        background_color: Srgba::BLACK.with_alpha(0.8).into(),
-        border_radius: BorderRadius::all(Val::Px(10.)),
         ..default()
     })
     .with_children(|parent| {
-        parent
-            .spawn(TextBundle::from_section(
-                "Autopilot: Off",
-                TextStyle {
-                    font_size: 30.,
-                    color: Srgba::WHITE.into(),
-                    ..default()
-                },
-            ))
-            .insert(AutopilotButtonText);
+        spawn_button(parent, AutopilotButton::Toggle);
+        spawn_button(parent, AutopilotButton::SwitchController);
+        spawn_button(parent, AutopilotButton::Hints);
     });
 }
 
-fn toggle_autopilot(mut autopilot: ResMut<Autopilot>, input: Res<ButtonInput<KeyCode>>) {
-    if input.just_pressed(KeyCode::KeyP) {
+fn spawn_button(parent: &mut ChildBuilder, button: AutopilotButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                flex_basis: Val::Px(150.),
+                flex_shrink: 0.,
+                padding: UiRect::all(Val::Px(5.)),
+                ..default()
+            },
+            background_color: Srgba::BLACK.with_alpha(0.8).into(),
+            border_radius: BorderRadius::all(Val::Px(10.)),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        color: Srgba::WHITE.into(),
+                        ..default()
+                    },
+                ))
+                .insert(AutopilotButtonText(button));
+        });
+}
+
+fn toggle_autopilot(mut autopilot: ResMut<Autopilot>, actions: Res<ButtonInput<Action>>) {
+    if actions.just_pressed(Action::ToggleAutopilot) {
         autopilot.0 = !autopilot.0;
     }
 }
 
-fn update_autopilot_button(
+fn toggle_hints(mut hints: ResMut<Hints>, actions: Res<ButtonInput<Action>>) {
+    if actions.just_pressed(Action::ToggleHints) {
+        hints.0 = !hints.0;
+    }
+}
+
+fn switch_controller_on_key(
+    mut active: ResMut<ActiveController>,
+    game: Res<Game>,
+    actions: Res<ButtonInput<Action>>,
+    registry: Res<ControllerRegistry>,
+) {
+    if actions.just_pressed(Action::SwitchController) {
+        switch_controller(&mut active, &registry, Some(&game));
+    }
+}
+
+fn update_autopilot_buttons(
     autopilot: Res<Autopilot>,
-    mut query: Query<&mut Text, With<AutopilotButtonText>>,
+    hints: Res<Hints>,
+    active: Res<ActiveController>,
+    mut query: Query<(&mut Text, &AutopilotButtonText)>,
 ) {
-    let mut text = query.single_mut();
-    if autopilot.0 {
-        text.sections[0].value = "Autopilot: On".to_string();
-    } else {
-        text.sections[0].value = "Autopilot: Off".to_string();
+    if !autopilot.is_changed() && !hints.is_changed() && !active.is_changed() {
+        return;
+    }
+
+    for (mut text, button) in &mut query {
+        text.sections[0].value = match button.0 {
+            AutopilotButton::Toggle if autopilot.0 => "Autopilot: On".to_string(),
+            AutopilotButton::Toggle => "Autopilot: Off".to_string(),
+            AutopilotButton::SwitchController => format!("Bot: {}", active.label),
+            AutopilotButton::Hints if hints.0 => "Hints: On".to_string(),
+            AutopilotButton::Hints => "Hints: Off".to_string(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{StepOutcome, WallMode},
+        pathfinding::Pathfinding,
+    };
+
+    /// Whether moving `direction` from the head lands somewhere the snake
+    /// survives. The tail counts as body, as it does when the game moves.
+    fn is_safe(game: &GameState, direction: Direction) -> bool {
+        let occupancy = game.occupancy();
+        occupancy
+            .neighbor(game.head(), direction)
+            .is_some_and(|next| occupancy.is_free(next))
+    }
+
+    #[test]
+    fn suggests_a_safe_move_from_the_current_head() {
+        for seed in 0..10 {
+            let mut game = GameState::new(16, 12, WallMode::Solid, 5, seed);
+            let mut controller = Pathfinding::default();
+            controller.reset(&game);
+            for _ in 0..300 {
+                let suggestion = suggest_move(&mut controller, &game);
+                if Direction::ALL.into_iter().any(|d| is_safe(&game, d)) {
+                    assert!(is_safe(&game, suggestion), "seed {seed}");
+                }
+                match game.step(suggestion) {
+                    StepOutcome::Moved | StepOutcome::Ate => {}
+                    _ => break,
+                }
+            }
+        }
     }
 }
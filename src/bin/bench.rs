@@ -0,0 +1,270 @@
+//! Plays seeded games with the autopilot's controllers without opening a
+//! window and reports how well each one did, so changes to a bot can be
+//! measured and compared across commits.
+
+use clap::Parser;
+use cpu_time::ThreadTime;
+use serde::Serialize;
+use snake::{
+    config::GameConfig,
+    constants::*,
+    controller::{ControllerEntry, ControllerRegistry},
+    game::{DeathCause, GameState, StepOutcome, WallMode},
+};
+use std::{process::ExitCode, time::Duration};
+
+/// Benchmarks the autopilot's controllers on headless games.
+#[derive(Parser, Debug)]
+#[command(about)]
+struct Args {
+    /// Controller to benchmark. Can be given more than once; every
+    /// controller is benchmarked if it isn't given at all
+    #[arg(long = "controller", value_name = "NAME")]
+    controllers: Vec<String>,
+
+    /// Number of games each controller plays
+    #[arg(long, default_value_t = 100)]
+    games: u64,
+
+    /// Seed of the first game. Each further game uses the next seed up,
+    /// wrapping around after the largest
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Board width in cells
+    #[arg(long, default_value_t = GRID_WIDTH)]
+    width: i32,
+
+    /// Board height in cells
+    #[arg(long, default_value_t = GRID_HEIGHT)]
+    height: i32,
+
+    /// solid to die at the edges, or wrap to come back in on the other side
+    #[arg(long, value_name = "MODE", default_value = "solid")]
+    wall_mode: WallMode,
+
+    /// Number of segments the snake starts with
+    #[arg(long, default_value_t = SNAKE_LENGTH)]
+    length: usize,
+
+    /// Give up on a game after this many moves without eating. Defaults to
+    /// four times the number of cells on the board
+    #[arg(long, value_name = "MOVES")]
+    stall_limit: Option<u64>,
+
+    /// Print the results as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+    Won,
+    Died(DeathCause),
+    /// Went `stall_limit` moves without eating.
+    Stalled,
+}
+
+struct GameResult {
+    outcome: Outcome,
+    length: usize,
+    score: u32,
+    moves: u64,
+    /// CPU time spent deciding on the slowest move.
+    slowest_decision: Duration,
+    /// CPU time spent deciding on moves in total.
+    decision_time: Duration,
+}
+
+#[derive(Serialize)]
+struct Report {
+    controller: &'static str,
+    width: i32,
+    height: i32,
+    wall_mode: WallMode,
+    snake_length: usize,
+    first_seed: u64,
+    games: usize,
+    mean_length: f64,
+    median_length: f64,
+    max_length: usize,
+    won: usize,
+    wall_deaths: usize,
+    body_deaths: usize,
+    stalled: usize,
+    moves_per_food: f64,
+    /// Mean CPU time spent picking a move, in microseconds.
+    mean_decision_us: f64,
+    /// CPU time spent picking the slowest move, in microseconds.
+    max_decision_us: f64,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let registry = ControllerRegistry::default();
+
+    let entries: Result<Vec<_>, _> = if args.controllers.is_empty() {
+        Ok(registry.iter().collect())
+    } else {
+        args.controllers
+            .iter()
+            .map(|name| registry.find(name).ok_or(name))
+            .collect()
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(name) => {
+            let names: Vec<_> = registry.iter().map(|e| e.name).collect();
+            eprintln!(
+                "Unknown controller `{name}`, expected one of: {}",
+                names.join(", ")
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let config = GameConfig {
+        grid_width: args.width,
+        grid_height: args.height,
+        wall_mode: args.wall_mode,
+        snake_length: args.length,
+        ..GameConfig::default()
+    }
+    .sanitized();
+    let cells = (config.grid_width * config.grid_height) as u64;
+    let stall_limit = args.stall_limit.unwrap_or(4 * cells);
+
+    let reports: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let results: Vec<_> = (0..args.games)
+                .map(|i| play(entry, &config, args.seed.wrapping_add(i), stall_limit))
+                .collect();
+            report(entry, &config, args.seed, &results)
+        })
+        .collect();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    } else {
+        for report in &reports {
+            print_report(report);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Plays one game to the end with a fresh instance of the controller.
+fn play(entry: &ControllerEntry, config: &GameConfig, seed: u64, stall_limit: u64) -> GameResult {
+    let mut game = GameState::new(
+        config.grid_width,
+        config.grid_height,
+        config.wall_mode,
+        config.snake_length,
+        seed,
+    );
+    let mut controller = entry.create();
+    controller.reset(&game);
+
+    let mut moves = 0;
+    let mut moves_since_food = 0;
+    let mut slowest_decision = Duration::ZERO;
+    let mut decision_time = Duration::ZERO;
+
+    let outcome = loop {
+        // Time on this thread's clock, so other processes competing for the
+        // CPU don't make a controller look slower
+        let start = ThreadTime::now();
+        let direction = controller.next_direction(&game);
+        let elapsed = start.elapsed();
+        slowest_decision = slowest_decision.max(elapsed);
+        decision_time += elapsed;
+
+        moves += 1;
+        moves_since_food += 1;
+        match game.step(direction.unwrap_or(game.direction())) {
+            StepOutcome::Moved if moves_since_food >= stall_limit => break Outcome::Stalled,
+            StepOutcome::Moved => {}
+            StepOutcome::Ate => moves_since_food = 0,
+            StepOutcome::Won => break Outcome::Won,
+            StepOutcome::Died(cause) => break Outcome::Died(cause),
+        }
+    };
+
+    GameResult {
+        outcome,
+        length: game.body().len(),
+        score: game.score(),
+        moves,
+        slowest_decision,
+        decision_time,
+    }
+}
+
+fn report(
+    entry: &ControllerEntry,
+    config: &GameConfig,
+    first_seed: u64,
+    results: &[GameResult],
+) -> Report {
+    let games = results.len();
+    let mut lengths: Vec<_> = results.iter().map(|r| r.length).collect();
+    lengths.sort_unstable();
+
+    let median_length = match games {
+        0 => 0.,
+        _ if games.is_multiple_of(2) => (lengths[games / 2 - 1] + lengths[games / 2]) as f64 / 2.,
+        _ => lengths[games / 2] as f64,
+    };
+    let count = |outcome| results.iter().filter(|r| r.outcome == outcome).count();
+
+    let moves: u64 = results.iter().map(|r| r.moves).sum();
+    let foods: u64 = results.iter().map(|r| r.score as u64).sum();
+    let decision_time: Duration = results.iter().map(|r| r.decision_time).sum();
+    let slowest_decision = results.iter().map(|r| r.slowest_decision).max();
+
+    Report {
+        controller: entry.name,
+        width: config.grid_width,
+        height: config.grid_height,
+        wall_mode: config.wall_mode,
+        snake_length: config.snake_length,
+        first_seed,
+        games,
+        mean_length: lengths.iter().sum::<usize>() as f64 / games.max(1) as f64,
+        median_length,
+        max_length: lengths.last().copied().unwrap_or(0),
+        won: count(Outcome::Won),
+        wall_deaths: count(Outcome::Died(DeathCause::Wall)),
+        body_deaths: count(Outcome::Died(DeathCause::Body)),
+        stalled: count(Outcome::Stalled),
+        moves_per_food: moves as f64 / foods.max(1) as f64,
+        mean_decision_us: decision_time.as_secs_f64() * 1e6 / moves.max(1) as f64,
+        max_decision_us: slowest_decision.unwrap_or_default().as_secs_f64() * 1e6,
+    }
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "{} on {}x{} ({} walls), {} games from seed {}",
+        report.controller,
+        report.width,
+        report.height,
+        format!("{:?}", report.wall_mode).to_lowercase(),
+        report.games,
+        report.first_seed
+    );
+    println!(
+        "  length     mean {:.1}, median {:.1}, max {}",
+        report.mean_length, report.median_length, report.max_length
+    );
+    println!(
+        "  outcome    {} won, {} hit a wall, {} hit the body, {} stalled",
+        report.won, report.wall_deaths, report.body_deaths, report.stalled
+    );
+    println!("  moves/food {:.1}", report.moves_per_food);
+    println!(
+        "  decision   mean {:.1} µs, max {:.1} µs",
+        report.mean_decision_us, report.max_decision_us
+    );
+}
@@ -0,0 +1,280 @@
+//! Plays games with the autopilot without opening a window, one after
+//! another, and streams every move to the spectators connected to it so the
+//! games can be watched from other machines with `snake --watch`.
+
+use clap::Parser;
+use snake::{
+    config::{GameConfig, RivalConfig},
+    constants::*,
+    controller::{ControllerRegistry, SnakeController},
+    game::{Direction, GameState, WallMode},
+    rival::{Difficulty, RivalController},
+    spectator::{Broadcast, Snapshot},
+};
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Longest a spectator can hold up a move before it's dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Pause between one game ending and the next starting, so spectators see
+/// how it ended.
+const GAME_OVER_PAUSE: Duration = Duration::from_secs(3);
+
+/// Plays autopilot games forever for spectators to watch.
+#[derive(Parser, Debug)]
+#[command(about)]
+struct Args {
+    /// Address to listen for spectators on. Use 0.0.0.0:7878 to let other
+    /// machines connect
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:7878")]
+    listen: String,
+
+    /// Bot that steers the snake
+    #[arg(long, value_name = "NAME", default_value = "pathfinding")]
+    controller: String,
+
+    /// Add a computer-controlled rival snake: easy, normal or hard. Can be
+    /// given more than once for several rivals
+    #[arg(long = "rival", value_name = "DIFFICULTY")]
+    rivals: Vec<Difficulty>,
+
+    /// Seed of the first game. Each further game uses the next seed up,
+    /// wrapping around after the largest
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Board width in cells
+    #[arg(long, default_value_t = GRID_WIDTH)]
+    width: i32,
+
+    /// Board height in cells
+    #[arg(long, default_value_t = GRID_HEIGHT)]
+    height: i32,
+
+    /// solid to die at the edges, or wrap to come back in on the other side
+    #[arg(long, value_name = "MODE", default_value = "solid")]
+    wall_mode: WallMode,
+
+    /// Number of segments the snake starts with
+    #[arg(long, default_value_t = SNAKE_LENGTH)]
+    length: usize,
+
+    /// Moves per second at the start of a game
+    #[arg(long, default_value_t = INITIAL_SPEED)]
+    speed: f32,
+
+    /// Highest speed in moves per second
+    #[arg(long, default_value_t = MAX_SPEED)]
+    max_speed: f32,
+
+    /// Give up on a game after this many moves without eating. Defaults to
+    /// four times the number of cells on the board
+    #[arg(long, value_name = "MOVES")]
+    stall_limit: Option<u64>,
+}
+
+/// The spectators currently connected.
+type Spectators = Arc<Mutex<Vec<TcpStream>>>;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let registry = ControllerRegistry::default();
+
+    let Some(entry) = registry.find(&args.controller) else {
+        let names: Vec<_> = registry.iter().map(|e| e.name).collect();
+        eprintln!(
+            "Unknown controller `{}`, expected one of: {}",
+            args.controller,
+            names.join(", ")
+        );
+        return ExitCode::FAILURE;
+    };
+
+    let config = GameConfig {
+        grid_width: args.width,
+        grid_height: args.height,
+        wall_mode: args.wall_mode,
+        snake_length: args.length,
+        initial_speed: args.speed,
+        max_speed: args.max_speed,
+        autopilot: true,
+        autopilot_controller: entry.name.to_string(),
+        players: 1,
+        rivals: args
+            .rivals
+            .iter()
+            .enumerate()
+            .map(|(i, &difficulty)| RivalConfig::new(i, difficulty))
+            .collect(),
+        ..GameConfig::default()
+    }
+    .sanitized();
+    let cells = (config.grid_width * config.grid_height) as u64;
+    let stall_limit = args.stall_limit.unwrap_or(4 * cells);
+
+    let listener = match TcpListener::bind(&args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Couldn't listen on {}: {e}", args.listen);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Waiting for spectators on {}", args.listen);
+
+    let spectators = Spectators::default();
+    // Spectators don't need to know how anyone steers
+    let hello = Broadcast::Config(GameConfig {
+        controls: Vec::new(),
+        ..config.clone()
+    });
+    let hello = serde_json::to_string(&hello).unwrap();
+    let accepted = spectators.clone();
+    thread::spawn(move || accept_spectators(listener, hello, accepted));
+
+    for game in 0.. {
+        play(
+            entry.create(),
+            &config,
+            game,
+            args.seed.wrapping_add(game),
+            stall_limit,
+            &spectators,
+        );
+        thread::sleep(GAME_OVER_PAUSE);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Sends every spectator that connects the server's settings and adds it to
+/// the ones snapshots are sent to.
+fn accept_spectators(listener: TcpListener, hello: String, spectators: Spectators) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let address = stream
+            .peer_addr()
+            .map_or("a spectator".to_string(), |address| address.to_string());
+        let greeted = stream
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .and_then(|()| stream.set_nodelay(true))
+            .and_then(|()| writeln!(stream, "{hello}"));
+        match greeted {
+            Ok(()) => {
+                println!("{address} is watching");
+                spectators.lock().unwrap().push(stream);
+            }
+            Err(e) => println!("Couldn't greet {address}: {e}"),
+        }
+    }
+}
+
+/// Sends `snapshot` to every spectator, dropping the ones it can't reach.
+fn broadcast(snapshot: Snapshot, spectators: &Spectators) {
+    let line = serde_json::to_string(&Broadcast::Snapshot(snapshot)).unwrap();
+    spectators
+        .lock()
+        .unwrap()
+        .retain_mut(|stream| writeln!(stream, "{line}").is_ok());
+}
+
+/// Plays one game to the end, at the speed it would be played in the game,
+/// and prints how it went.
+fn play(
+    mut controller: Box<dyn SnakeController>,
+    config: &GameConfig,
+    game_number: u64,
+    seed: u64,
+    stall_limit: u64,
+    spectators: &Spectators,
+) {
+    let mut game = GameState::with_snakes(
+        config.grid_width,
+        config.grid_height,
+        config.wall_mode,
+        config.snake_length,
+        config.snakes(),
+        seed,
+    );
+    controller.reset(&game);
+    let mut rivals: Vec<_> = config
+        .rivals
+        .iter()
+        .enumerate()
+        .map(|(i, rival)| {
+            let id = config.players + i;
+            (
+                id,
+                RivalController::new(rival.difficulty, seed.wrapping_add(id as u64)),
+            )
+        })
+        .collect();
+
+    let mut moves = 0;
+    let mut moves_since_food = 0;
+    broadcast(Snapshot::new(game_number, moves, &game), spectators);
+
+    let stalled = loop {
+        let interval = 1. / config.speed(game.food_eaten());
+        thread::sleep(Duration::from_secs_f32(interval));
+
+        let mut directions: Vec<Direction> = game
+            .snakes()
+            .iter()
+            .map(|snake| snake.direction())
+            .collect();
+        if game.snakes()[0].is_alive() {
+            if let Some(direction) = controller.next_direction(&game) {
+                directions[0] = direction;
+            }
+        }
+        for (id, rival) in &mut rivals {
+            if game.snakes()[*id].is_alive() {
+                if let Some(direction) = rival.next_direction(&game.seen_by(*id)) {
+                    directions[*id] = direction;
+                }
+            }
+        }
+
+        let eaten = game.food_eaten();
+        game.step_all(&directions);
+        moves += 1;
+        moves_since_food = if game.food_eaten() > eaten {
+            0
+        } else {
+            moves_since_food + 1
+        };
+        broadcast(Snapshot::new(game_number, moves, &game), spectators);
+
+        if game.is_over() || !game.snakes()[0].is_alive() {
+            break false;
+        }
+        if moves_since_food >= stall_limit {
+            break true;
+        }
+    };
+
+    let outcome = if stalled {
+        "stalled".to_string()
+    } else if game.won() {
+        "won".to_string()
+    } else {
+        match game.snakes()[0].death() {
+            Some(cause) => format!("died ({cause:?})").to_lowercase(),
+            None => "ended".to_string(),
+        }
+    };
+    println!(
+        "Game {} (seed {seed}) {outcome} after {moves} moves, length {}, score {}",
+        game_number + 1,
+        game.body().len(),
+        game.score()
+    );
+}
@@ -1,19 +1,43 @@
-use crate::constants::*;
+use crate::{
+    config::GameConfig,
+    snake::{Game, SetupSnakeSet},
+    state::AppState,
+};
 use bevy::{prelude::*, render::camera::ScalingMode};
 
 pub struct CameraPlugin;
 
+#[derive(Component)]
+struct PlayArea;
+
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (setup_camera, setup_clear_color, setup_play_area));
+        app.add_systems(Startup, (setup_camera, setup_clear_color, setup_play_area))
+            .add_systems(
+                OnEnter(AppState::Playing),
+                fit_to_board.after(SetupSnakeSet),
+            );
     }
 }
 
-fn setup_camera(mut cmd: Commands) {
+/// Where the camera has to look, and how much it has to see, for a board of
+/// the given size to fill the window with (0,0) in the bottom left.
+fn board_view(width: f32, height: f32) -> (Transform, ScalingMode) {
+    (
+        Transform::from_xyz(width / 2. - 0.5, height / 2. - 0.5, 0.),
+        ScalingMode::AutoMin {
+            min_width: width,
+            min_height: height,
+        },
+    )
+}
+
+fn setup_camera(mut cmd: Commands, config: Res<GameConfig>) {
+    let (transform, scaling_mode) = board_view(config.grid_width as f32, config.grid_height as f32);
+
     let mut camera = Camera2dBundle::default();
-    camera.projection.scaling_mode = ScalingMode::FixedHorizontal(GRID_WIDTH);
-    // Pan the camera so that (0,0) is in the bottom left, assuming a 16x9 aspect ratio
-    camera.transform = Transform::from_xyz(GRID_WIDTH / 2. - 0.5, GRID_HEIGHT / 2. - 0.5, 0.);
+    camera.projection.scaling_mode = scaling_mode;
+    camera.transform = transform;
     cmd.spawn(camera);
 }
 
@@ -21,15 +45,42 @@ fn setup_clear_color(mut cmd: Commands) {
     cmd.insert_resource(ClearColor(Srgba::gray(0.5).into()));
 }
 
-fn setup_play_area(mut cmd: Commands) {
+fn setup_play_area(mut cmd: Commands, config: Res<GameConfig>) {
+    let (width, height) = (config.grid_width as f32, config.grid_height as f32);
+
     cmd.spawn(SpriteBundle {
         sprite: Sprite {
             color: Color::BLACK,
-            custom_size: Some(Vec2::new(GRID_WIDTH, GRID_HEIGHT)),
+            custom_size: Some(Vec2::new(width, height)),
             ..default()
         },
         // Move the play area so the bottom left corner is at (0,0)
-        transform: Transform::from_xyz(GRID_WIDTH / 2. - 0.5, GRID_HEIGHT / 2. - 0.5, -1.),
+        transform: Transform::from_xyz(width / 2. - 0.5, height / 2. - 0.5, -1.),
         ..default()
-    });
+    })
+    .insert(PlayArea);
+}
+
+fn fit_to_board(
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), Without<PlayArea>>,
+    mut play_area_q: Query<(&mut Transform, &mut Sprite), With<PlayArea>>,
+    game: Res<Game>,
+) {
+    // Starting straight into a game enters Playing before Startup has run,
+    // but then the camera gets spawned already fitting the configured board
+    let (Ok((mut camera_transform, mut projection)), Ok((mut play_area_transform, mut sprite))) =
+        (camera_q.get_single_mut(), play_area_q.get_single_mut())
+    else {
+        return;
+    };
+
+    let occupancy = game.0.occupancy();
+    let (width, height) = (occupancy.width() as f32, occupancy.height() as f32);
+    let (transform, scaling_mode) = board_view(width, height);
+
+    *camera_transform = transform;
+    projection.scaling_mode = scaling_mode;
+
+    play_area_transform.translation = transform.translation.with_z(-1.);
+    sprite.custom_size = Some(Vec2::new(width, height));
 }
@@ -0,0 +1,117 @@
+use crate::{
+    config::{ControlScheme, SpeedCurve},
+    game::WallMode,
+    rival::Difficulty,
+};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Snake, with an optional autopilot.
+///
+/// Every flag overrides the matching setting from the config file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file to load instead of the one in the user's config directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Board width in cells
+    #[arg(long)]
+    pub width: Option<i32>,
+
+    /// Board height in cells
+    #[arg(long)]
+    pub height: Option<i32>,
+
+    /// solid to die at the edges, or wrap to come back in on the other side
+    #[arg(long, value_name = "MODE")]
+    pub wall_mode: Option<WallMode>,
+
+    /// Number of segments the snake starts with
+    #[arg(long)]
+    pub length: Option<usize>,
+
+    /// Moves per second at the start of a game
+    #[arg(long)]
+    pub speed: Option<f32>,
+
+    /// multiply:<factor>, linear:<increase> or stepped:<every>:<increase>
+    #[arg(long, value_name = "CURVE")]
+    pub speed_curve: Option<SpeedCurve>,
+
+    /// Highest speed in moves per second
+    #[arg(long)]
+    pub max_speed: Option<f32>,
+
+    /// Whether the autopilot starts switched on
+    #[arg(long, value_name = "BOOL")]
+    pub autopilot: Option<bool>,
+
+    /// Bot that steers while the autopilot is on: pathfinding or cycle
+    #[arg(long, value_name = "NAME")]
+    pub autopilot_controller: Option<String>,
+
+    /// Whether hints start switched on, showing the move the autopilot
+    /// would make while you keep control
+    #[arg(long, value_name = "BOOL")]
+    pub hints: Option<bool>,
+
+    /// Number of people playing on this machine, from 1 to 4
+    #[arg(long)]
+    pub players: Option<usize>,
+
+    /// Add a computer-controlled rival snake: easy, normal or hard. Can be
+    /// given more than once for several rivals
+    #[arg(long = "rival", value_name = "DIFFICULTY")]
+    pub rivals: Vec<Difficulty>,
+
+    /// absolute to steer with all four directions, or relative to turn left
+    /// and right from the way the snake is heading. Applies to the first
+    /// player
+    #[arg(long, value_name = "SCHEME")]
+    pub control_scheme: Option<ControlScheme>,
+
+    /// Seed for the game's RNG, to replay the same food placement
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Write a replay of the first finished game to this file, and of every
+    /// later one to a numbered file next to it, such as game-2.ron for game.ron
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Play back a recorded replay instead of a live game
+    #[arg(long, value_name = "PATH", conflicts_with = "seed")]
+    pub replay: Option<PathBuf>,
+
+    /// Host a network game on this UDP port for another player to join
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["join", "replay"])]
+    pub host: Option<u16>,
+
+    /// Join the network game hosted at this address, such as
+    /// 192.168.1.5:7777
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "replay")]
+    pub join: Option<String>,
+
+    /// Watch the games played by the server at this address, such as
+    /// 127.0.0.1:7878, instead of playing
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["replay", "host", "join"])]
+    pub watch: Option<String>,
+
+    /// Moves each turn is held back by in a network game, to hide the time
+    /// it takes to reach the other player
+    #[arg(long, value_name = "MOVES")]
+    pub input_delay: Option<u32>,
+
+    /// Let an external program steer: a command that reads the board as a
+    /// line of JSON before each move and answers with up, down, left or
+    /// right. Switches the autopilot on and over to it
+    #[arg(long, value_name = "COMMAND")]
+    pub bot: Option<String>,
+
+    /// Milliseconds the external bot gets to answer each board. The game never
+    /// waits for it, so answers have to arrive before the move is due as well
+    #[arg(long, value_name = "MS")]
+    pub bot_timeout: Option<u64>,
+}
@@ -0,0 +1,520 @@
+use crate::{
+    actions::{Action, Binding, Bindings},
+    cli::Cli,
+    constants::*,
+    external::EXTERNAL_CONTROLLER,
+    game::WallMode,
+    rival::Difficulty,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// How the snake's speed grows as it eats.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpeedCurve {
+    /// Multiply the speed by this factor for every food eaten.
+    Multiply(f32),
+    /// Add this many moves per second for every food eaten.
+    Linear(f32),
+    /// Add `increase` moves per second once every `every` foods.
+    Stepped { every: u32, increase: f32 },
+}
+
+impl SpeedCurve {
+    /// Speed after `score` foods for a game that started at `initial`.
+    pub fn speed(self, initial: f32, score: u32) -> f32 {
+        match self {
+            SpeedCurve::Multiply(factor) => initial * factor.powi(score as i32),
+            SpeedCurve::Linear(increase) => initial + increase * score as f32,
+            SpeedCurve::Stepped { every, increase } => {
+                initial + increase * (score / every.max(1)) as f32
+            }
+        }
+    }
+
+    /// The same curve with a factor that keeps the speed positive and
+    /// increases that never slow the snake down.
+    fn sanitized(self) -> SpeedCurve {
+        match self {
+            SpeedCurve::Multiply(factor) if factor > 0. => SpeedCurve::Multiply(factor),
+            SpeedCurve::Multiply(_) => SpeedCurve::Multiply(1.),
+            SpeedCurve::Linear(increase) => SpeedCurve::Linear(increase.max(0.)),
+            SpeedCurve::Stepped { every, increase } => SpeedCurve::Stepped {
+                every,
+                increase: increase.max(0.),
+            },
+        }
+    }
+}
+
+/// Parses `multiply:<factor>`, `linear:<increase>` or `stepped:<every>:<increase>`.
+impl FromStr for SpeedCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(':').collect();
+        let number = |part: &str| {
+            part.parse()
+                .map_err(|_| format!("invalid number `{part}` in speed curve `{s}`"))
+        };
+
+        let curve = match parts[..] {
+            ["multiply", factor] => SpeedCurve::Multiply(number(factor)?),
+            ["linear", increase] => SpeedCurve::Linear(number(increase)?),
+            ["stepped", every, increase] => SpeedCurve::Stepped {
+                every: every
+                    .parse()
+                    .map_err(|_| format!("invalid step `{every}` in speed curve `{s}`"))?,
+                increase: number(increase)?,
+            },
+            _ => {
+                return Err(format!(
+                    "expected multiply:<factor>, linear:<increase> or \
+                     stepped:<every>:<increase>, got `{s}`"
+                ))
+            }
+        };
+        // The snake would come to a stop, or go backwards in time
+        match curve {
+            SpeedCurve::Multiply(factor) if factor > 0. => Ok(curve),
+            SpeedCurve::Multiply(_) => {
+                Err(format!("the factor in speed curve `{s}` must be above 0"))
+            }
+            SpeedCurve::Linear(increase) | SpeedCurve::Stepped { increase, .. }
+                if increase >= 0. =>
+            {
+                Ok(curve)
+            }
+            _ => Err(format!(
+                "the increase in speed curve `{s}` can't be negative"
+            )),
+        }
+    }
+}
+
+/// How the direction keys steer the snake.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Each of Up, Down, Left and Right sends the snake that way.
+    #[default]
+    Absolute,
+    /// Left and Right turn the snake a quarter turn from the way it's
+    /// heading, so two buttons are enough to play.
+    Relative,
+}
+
+/// Parses `absolute` or `relative`.
+impl FromStr for ControlScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(ControlScheme::Absolute),
+            "relative" => Ok(ControlScheme::Relative),
+            _ => Err(format!("expected absolute or relative, got `{s}`")),
+        }
+    }
+}
+
+/// How one player steers their snake.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerControls {
+    pub control_scheme: ControlScheme,
+    pub bindings: Bindings,
+}
+
+impl PlayerControls {
+    /// The controls `player` starts out with, counting from 0.
+    pub fn for_player(player: usize) -> PlayerControls {
+        PlayerControls {
+            control_scheme: ControlScheme::Absolute,
+            bindings: Bindings::for_player(player),
+        }
+    }
+}
+
+impl Default for PlayerControls {
+    fn default() -> Self {
+        PlayerControls::for_player(0)
+    }
+}
+
+/// Colours rivals are given by default, first rival first.
+const RIVAL_COLORS: [Srgba; 4] = [
+    Srgba::rgb(0.4, 0.9, 0.3),
+    Srgba::rgb(1., 0.5, 0.2),
+    Srgba::rgb(0.7, 0.5, 1.),
+    Srgba::rgb(0.2, 0.9, 0.8),
+];
+
+/// A computer-controlled snake that competes with the players.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RivalConfig {
+    pub difficulty: Difficulty,
+    pub color: Srgba,
+}
+
+impl RivalConfig {
+    /// A rival with the default colour for the `i`th rival, counting from 0.
+    pub fn new(i: usize, difficulty: Difficulty) -> RivalConfig {
+        RivalConfig {
+            difficulty,
+            color: RIVAL_COLORS[i % RIVAL_COLORS.len()],
+        }
+    }
+}
+
+impl Default for RivalConfig {
+    fn default() -> Self {
+        RivalConfig::new(0, Difficulty::Normal)
+    }
+}
+
+/// Parses `easy`, `normal` or `hard`.
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("expected easy, normal or hard, got `{s}`")),
+        }
+    }
+}
+
+/// Parses `solid` or `wrap`.
+impl FromStr for WallMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solid" => Ok(WallMode::Solid),
+            "wrap" => Ok(WallMode::Wrap),
+            _ => Err(format!("expected solid or wrap, got `{s}`")),
+        }
+    }
+}
+
+/// Settings that new games are started with.
+///
+/// Loaded from a RON file in the user's config directory and then overridden
+/// by any command-line flags. Fields missing from the file keep their
+/// defaults.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub grid_width: i32,
+    pub grid_height: i32,
+    pub wall_mode: WallMode,
+    pub snake_length: usize,
+    /// Moves per second at the start of a game.
+    pub initial_speed: f32,
+    pub speed_curve: SpeedCurve,
+    /// The speed never grows past this many moves per second.
+    pub max_speed: f32,
+    /// Whether the autopilot is switched on when a game starts.
+    pub autopilot: bool,
+    /// Name of the controller that steers the snake while the autopilot is
+    /// on.
+    pub autopilot_controller: String,
+    /// Whether the autopilot's suggested move is shown when a game starts.
+    pub hints: bool,
+    /// Seed for every game's RNG. A fresh random seed is picked for each
+    /// game if this isn't set.
+    pub seed: Option<u64>,
+    /// Number of people playing on this machine, each steering their own
+    /// snake.
+    pub players: usize,
+    /// How each player steers, first player first. Every player the game
+    /// supports has an entry, however many are playing.
+    pub controls: Vec<PlayerControls>,
+    /// Computer-controlled snakes joining the players' on the board.
+    pub rivals: Vec<RivalConfig>,
+    /// Moves a turn is held back by in a network game, giving it time to
+    /// reach the other player before the snake has to make it. The host's
+    /// setting is used on both sides.
+    pub input_delay: u32,
+    /// Command that starts an external bot, which the autopilot can then be
+    /// switched to. See `external` for how the game talks to it.
+    pub bot: Option<String>,
+    /// Milliseconds the external bot gets to answer before the snake keeps
+    /// going the way it's heading.
+    pub bot_timeout: u64,
+}
+
+/// Where the config file was loaded from, so changes made in the game can be
+/// saved back to it.
+#[derive(Resource)]
+pub struct ConfigPath(pub PathBuf);
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            grid_width: GRID_WIDTH,
+            grid_height: GRID_HEIGHT,
+            wall_mode: WallMode::Solid,
+            snake_length: SNAKE_LENGTH,
+            initial_speed: INITIAL_SPEED,
+            speed_curve: SpeedCurve::Multiply(SPEED_UP),
+            max_speed: MAX_SPEED,
+            autopilot: false,
+            autopilot_controller: "pathfinding".to_string(),
+            hints: false,
+            seed: None,
+            players: 1,
+            controls: (0..MAX_PLAYERS).map(PlayerControls::for_player).collect(),
+            rivals: Vec::new(),
+            input_delay: INPUT_DELAY,
+            bot: None,
+            bot_timeout: BOT_TIMEOUT,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Loads the config file named on the command line, or the default one,
+    /// and applies the command-line overrides on top.
+    pub fn from_cli(cli: &Cli) -> GameConfig {
+        let mut config = GameConfig::load(&config_path(cli));
+
+        if let Some(width) = cli.width {
+            config.grid_width = width;
+        }
+        if let Some(height) = cli.height {
+            config.grid_height = height;
+        }
+        if let Some(wall_mode) = cli.wall_mode {
+            config.wall_mode = wall_mode;
+        }
+        if let Some(length) = cli.length {
+            config.snake_length = length;
+        }
+        if let Some(speed) = cli.speed {
+            config.initial_speed = speed;
+        }
+        if let Some(speed_curve) = cli.speed_curve {
+            config.speed_curve = speed_curve;
+        }
+        if let Some(max_speed) = cli.max_speed {
+            config.max_speed = max_speed;
+        }
+        if let Some(autopilot) = cli.autopilot {
+            config.autopilot = autopilot;
+        }
+        if let Some(controller) = &cli.autopilot_controller {
+            config.autopilot_controller = controller.clone();
+        }
+        if let Some(hints) = cli.hints {
+            config.hints = hints;
+        }
+        if cli.seed.is_some() {
+            config.seed = cli.seed;
+        }
+        if let Some(players) = cli.players {
+            config.players = players;
+        }
+        if !cli.rivals.is_empty() {
+            config.rivals = cli
+                .rivals
+                .iter()
+                .enumerate()
+                .map(|(i, &difficulty)| RivalConfig::new(i, difficulty))
+                .collect();
+        }
+        if let Some(input_delay) = cli.input_delay {
+            config.input_delay = input_delay;
+        }
+        if cli.bot.is_some() {
+            config.bot = cli.bot.clone();
+            config.autopilot_controller = EXTERNAL_CONTROLLER.to_string();
+            config.autopilot = cli.autopilot.unwrap_or(true);
+        }
+        if let Some(bot_timeout) = cli.bot_timeout {
+            config.bot_timeout = bot_timeout;
+        }
+
+        config.sanitize();
+        // Sanitizing made sure there are controls for every player
+        if let Some(control_scheme) = cli.control_scheme {
+            config.controls[0].control_scheme = control_scheme;
+        }
+        config
+    }
+
+    /// Reads the config file at `path`, falling back to the defaults if it
+    /// doesn't exist or can't be parsed.
+    pub fn load(path: &Path) -> GameConfig {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return GameConfig::default();
+        };
+
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid config file {}: {e}", path.display());
+            GameConfig::default()
+        })
+    }
+
+    /// Stores every player's controls from `controls` in the config file at
+    /// `path`, keeping the other settings in the file as they are.
+    pub fn save_controls(path: &Path, controls: &GameConfig) -> Result<(), String> {
+        let config = GameConfig {
+            controls: controls.controls.clone(),
+            ..GameConfig::load(path)
+        };
+        let contents = ron::ser::to_string_pretty(&config, default()).map_err(|e| e.to_string())?;
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, contents))
+            .map_err(|e| e.to_string())
+    }
+
+    /// The config with every setting clamped by `sanitize`.
+    pub fn sanitized(mut self) -> GameConfig {
+        self.sanitize();
+        self
+    }
+
+    /// Clamps every setting into a range a game can be started with.
+    pub fn sanitize(&mut self) {
+        self.grid_width = self.grid_width.clamp(4, 256);
+        self.grid_height = self.grid_height.clamp(4, 256);
+        // The snake starts in the middle of the board, stretching to the left
+        let max_length = self.grid_width as usize / 2 + 1;
+        self.snake_length = self.snake_length.clamp(1, max_length);
+        self.initial_speed = self.initial_speed.max(MIN_SPEED);
+        self.speed_curve = self.speed_curve.sanitized();
+        self.max_speed = self.max_speed.max(self.initial_speed);
+        self.input_delay = self.input_delay.min(10);
+        self.bot_timeout = self.bot_timeout.clamp(1, 5000);
+        // Every snake starts on a row of its own
+        let max_snakes = self.grid_height as usize - 1;
+        self.players = self.players.clamp(1, MAX_PLAYERS.min(max_snakes));
+        self.rivals
+            .truncate(MAX_RIVALS.min(max_snakes - self.players));
+        self.controls.truncate(MAX_PLAYERS);
+        while self.controls.len() < MAX_PLAYERS {
+            let player = self.controls.len();
+            self.controls.push(PlayerControls::for_player(player));
+        }
+        for (player, controls) in self.controls.iter_mut().enumerate() {
+            controls.bindings.add_missing(&Bindings::for_player(player));
+        }
+    }
+
+    /// The first key any player has bound to `action`, for showing it in the
+    /// UI.
+    pub fn key(&self, action: Action) -> Option<Binding> {
+        self.controls
+            .iter()
+            .find_map(|controls| controls.bindings.key(action))
+    }
+
+    /// Number of snakes in a game, the players' first and then the rivals'.
+    pub fn snakes(&self) -> usize {
+        self.players + self.rivals.len()
+    }
+
+    /// Moves per second once the snake has eaten `score` foods.
+    pub fn speed(&self, score: u32) -> f32 {
+        self.speed_curve
+            .speed(self.initial_speed, score)
+            .min(self.max_speed)
+            .max(MIN_SPEED)
+    }
+}
+
+/// The config file named on the command line, or the default one.
+pub fn config_path(cli: &Cli) -> PathBuf {
+    cli.config.clone().unwrap_or_else(default_config_path)
+}
+
+pub fn default_config_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_default()
+        .join("snake")
+        .join("config.ron")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_speed_curves() {
+        assert_eq!("multiply:1.5".parse(), Ok(SpeedCurve::Multiply(1.5)));
+        assert_eq!("linear:0.5".parse(), Ok(SpeedCurve::Linear(0.5)));
+        assert_eq!(
+            "stepped:5:2".parse(),
+            Ok(SpeedCurve::Stepped {
+                every: 5,
+                increase: 2.
+            })
+        );
+        for bad in [
+            "multiply:0",
+            "multiply:-2",
+            "multiply:NaN",
+            "linear:-1",
+            "stepped:5:-0.5",
+            "linear",
+            "linear:fast",
+            "curved:2",
+        ] {
+            assert!(bad.parse::<SpeedCurve>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn speed_stays_positive() {
+        for speed_curve in [
+            SpeedCurve::Multiply(0.),
+            SpeedCurve::Multiply(0.5),
+            SpeedCurve::Linear(-1.),
+            SpeedCurve::Stepped {
+                every: 1,
+                increase: -3.,
+            },
+        ] {
+            let mut config = GameConfig {
+                initial_speed: -4.,
+                speed_curve,
+                ..GameConfig::default()
+            };
+            config.sanitize();
+            for score in [0, 1, 10, 1000] {
+                let speed = config.speed(score);
+                assert!(speed >= MIN_SPEED, "{speed_curve:?} at {score}: {speed}");
+                assert!(speed <= config.max_speed);
+            }
+        }
+    }
+
+    #[test]
+    fn binds_actions_missing_from_older_configs() {
+        let mut config = GameConfig::default();
+        let bindings = &mut config.controls[0].bindings;
+        // Saved before hints had an action, with H taken for something else
+        *bindings = ron::from_str("({Pause: [Key(KeyH)]})").unwrap();
+        config.sanitize();
+
+        let bindings = &config.controls[0].bindings;
+        assert_eq!(bindings.get(Action::Pause), [Binding::Key(KeyCode::KeyH)]);
+        assert_eq!(
+            bindings.get(Action::ToggleHints),
+            [Binding::GamepadButton(GamepadButtonType::West)]
+        );
+        assert_eq!(
+            bindings.key(Action::SwitchController),
+            Some(Binding::Key(KeyCode::KeyO))
+        );
+    }
+}
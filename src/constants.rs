@@ -1,4 +1,11 @@
-pub const GRID_HEIGHT: f32 = 36.;
-pub const GRID_WIDTH: f32 = 64.;
+pub const GRID_HEIGHT: i32 = 36;
+pub const GRID_WIDTH: i32 = 64;
 pub const SNAKE_LENGTH: usize = 5;
 pub const INITIAL_SPEED: f32 = 8.;
+pub const SPEED_UP: f32 = 1.05;
+pub const MAX_SPEED: f32 = 30.;
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_PLAYERS: usize = 4;
+pub const MAX_RIVALS: usize = 4;
+pub const INPUT_DELAY: u32 = 2;
+pub const BOT_TIMEOUT: u64 = 50;
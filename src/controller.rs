@@ -0,0 +1,129 @@
+//! Bots that can steer the snake.
+//!
+//! A bot implements `SnakeController` and is added to the list in
+//! `ControllerRegistry::default`. The autopilot then offers it in the
+//! settings, on the in-game button and under its name in the config, without
+//! the bot having to know anything about Bevy.
+
+use crate::{
+    game::{Direction, GameState},
+    hamiltonian::CycleController,
+    pathfinding::Pathfinding,
+};
+use bevy::prelude::*;
+
+/// Decides where the snake goes next by looking at the board.
+pub trait SnakeController: Send + Sync {
+    /// Called before the controller steers a game for the first time, either
+    /// because the game just started or because the controller took over
+    /// from another one.
+    fn reset(&mut self, _game: &GameState) {}
+
+    /// Called once the snake has moved, with the board the next move will be
+    /// made from, so controllers that need time can start thinking about it.
+    /// `next_direction` is called with the same board once the move is due.
+    fn prepare(&mut self, _game: &GameState) {}
+
+    /// Picks the direction for the next move, or `None` to keep going the
+    /// way the snake is already heading.
+    fn next_direction(&mut self, game: &GameState) -> Option<Direction>;
+
+    /// What the controller was up to when it picked its last move, for the
+    /// debug overlay. Controllers that don't keep track of this return `None`.
+    fn plan(&self) -> Option<&Plan> {
+        None
+    }
+}
+
+/// The reasoning behind a controller's last move.
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    /// What the controller was trying to do, e.g. "Chase food".
+    pub goal: &'static str,
+    /// Cells the snake is headed through, starting at the head.
+    pub path: Vec<IVec2>,
+    /// Cells looked at while searching for a path.
+    pub explored: Vec<IVec2>,
+    /// Cells the snake could still reach after each of the moves that were
+    /// weighed up.
+    pub areas: Vec<(Direction, Vec<IVec2>)>,
+}
+
+pub struct ControllerEntry {
+    /// Name used for the controller in the config and on the command line.
+    pub name: &'static str,
+    /// Name shown for the controller in menus.
+    pub label: &'static str,
+    create: Box<dyn Fn() -> Box<dyn SnakeController> + Send + Sync>,
+}
+
+/// Every controller the autopilot can be switched to, in menu order.
+#[derive(Resource)]
+pub struct ControllerRegistry {
+    entries: Vec<ControllerEntry>,
+}
+
+impl Default for ControllerRegistry {
+    fn default() -> Self {
+        let mut registry = ControllerRegistry {
+            entries: Vec::new(),
+        };
+        registry.register("pathfinding", "Pathfinding", || {
+            Box::new(Pathfinding::default())
+        });
+        registry.register("cycle", "Cycle", || Box::new(CycleController::default()));
+        registry
+    }
+}
+
+impl ControllerRegistry {
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        label: &'static str,
+        create: impl Fn() -> Box<dyn SnakeController> + Send + Sync + 'static,
+    ) {
+        self.entries.push(ControllerEntry {
+            name,
+            label,
+            create: Box::new(create),
+        });
+    }
+
+    /// The controller listed first, which the autopilot starts out with.
+    pub fn first(&self) -> &ControllerEntry {
+        &self.entries[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ControllerEntry> {
+        self.entries.iter()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ControllerEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Looks up a controller by name, falling back to the first one if there
+    /// is none by that name.
+    pub fn get(&self, name: &str) -> &ControllerEntry {
+        self.find(name).unwrap_or(self.first())
+    }
+
+    /// The controller `step` places after the one called `name` in menu
+    /// order, wrapping around at either end.
+    pub fn step(&self, name: &str, step: i32) -> &ControllerEntry {
+        let current = self
+            .entries
+            .iter()
+            .position(|e| e.name == name)
+            .unwrap_or(0);
+        let len = self.entries.len() as i32;
+        &self.entries[(current as i32 + step).rem_euclid(len) as usize]
+    }
+}
+
+impl ControllerEntry {
+    pub fn create(&self) -> Box<dyn SnakeController> {
+        (self.create)()
+    }
+}
@@ -0,0 +1,167 @@
+//! Shows what the autopilot is thinking, to help work out why it made a
+//! strange move. F3 toggles it while playing, unless it's been rebound.
+//!
+//! The board shows the path the controller means to take, the cells its
+//! search looked at, and when it's fighting for survival, the open area it
+//! found behind each possible move. A panel names the goal behind the move.
+
+use crate::{
+    actions::Action,
+    autopilot::{draw_path, ActiveController, Autopilot, AutopilotSet, Hints},
+    game::Direction,
+    replay::Playback,
+    state::AppState,
+};
+use bevy::prelude::*;
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_debug_panel)
+            .add_systems(
+                Update,
+                (
+                    toggle_debug_overlay,
+                    (draw_plan, update_debug_panel).after(AutopilotSet),
+                )
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnExit(AppState::Playing), hide_debug_panel)
+            .insert_resource(DebugOverlay(false));
+    }
+}
+
+#[derive(Resource)]
+pub struct DebugOverlay(pub bool);
+
+#[derive(Component)]
+struct DebugPanel;
+
+#[derive(Component)]
+struct DebugPanelText;
+
+const PATH_COLOR: Srgba = Srgba::rgb(1., 1., 1.);
+const EXPLORED_COLOR: Srgba = Srgba::rgb(0.3, 0.5, 1.);
+
+/// Colour of the open area found behind each move.
+fn area_color(direction: Direction) -> Srgba {
+    match direction {
+        Direction::Up => Srgba::rgb(1., 0.3, 0.3),
+        Direction::Down => Srgba::rgb(0.3, 1., 0.3),
+        Direction::Left => Srgba::rgb(1., 0.3, 1.),
+        Direction::Right => Srgba::rgb(1., 0.85, 0.2),
+    }
+}
+
+/// Whether the controller is deciding moves, either steering or giving hints.
+fn controller_running(autopilot: &Autopilot, hints: &Hints, playback: Option<&Playback>) -> bool {
+    (autopilot.0 || hints.0) && playback.is_none()
+}
+
+fn toggle_debug_overlay(mut overlay: ResMut<DebugOverlay>, actions: Res<ButtonInput<Action>>) {
+    if actions.just_pressed(Action::ToggleDebugOverlay) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+fn draw_plan(
+    mut gizmos: Gizmos,
+    overlay: Res<DebugOverlay>,
+    autopilot: Res<Autopilot>,
+    hints: Res<Hints>,
+    active: Res<ActiveController>,
+    playback: Option<Res<Playback>>,
+) {
+    if !overlay.0 || !controller_running(&autopilot, &hints, playback.as_deref()) {
+        return;
+    }
+    let Some(plan) = active.plan() else {
+        return;
+    };
+
+    for &cell in &plan.explored {
+        gizmos.rect_2d(cell.as_vec2(), 0., Vec2::splat(0.3), EXPLORED_COLOR);
+    }
+
+    // Nest the outlines so a cell in several areas shows each of them
+    for (i, (direction, area)) in plan.areas.iter().enumerate() {
+        let size = Vec2::splat(0.9 - 0.15 * i as f32);
+        for &cell in area {
+            gizmos.rect_2d(cell.as_vec2(), 0., size, area_color(*direction));
+        }
+    }
+
+    draw_path(&mut gizmos, &plan.path, PATH_COLOR);
+    if let Some(end) = plan.path.last() {
+        gizmos.circle_2d(end.as_vec2(), 0.25, PATH_COLOR);
+    }
+}
+
+fn setup_debug_panel(mut cmd: Commands) {
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            bottom: Val::Px(10.),
+            padding: UiRect::all(Val::Px(5.)),
+            ..default()
+        },
+        background_color: Srgba::BLACK.with_alpha(0.8).into(),
+        border_radius: BorderRadius::all(Val::Px(10.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+    .insert(DebugPanel)
+    .with_children(|parent| {
+        parent
+            .spawn(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    color: Srgba::WHITE.into(),
+                    ..default()
+                },
+            ))
+            .insert(DebugPanelText);
+    });
+}
+
+fn update_debug_panel(
+    overlay: Res<DebugOverlay>,
+    autopilot: Res<Autopilot>,
+    hints: Res<Hints>,
+    active: Res<ActiveController>,
+    playback: Option<Res<Playback>>,
+    mut panel_q: Query<&mut Visibility, With<DebugPanel>>,
+    mut text_q: Query<&mut Text, With<DebugPanelText>>,
+) {
+    *panel_q.single_mut() = if overlay.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if !overlay.0 {
+        return;
+    }
+
+    let mut text = format!("Bot: {}\n", active.label());
+    if !controller_running(&autopilot, &hints, playback.as_deref()) {
+        text += "Autopilot and hints are off";
+    } else if let Some(plan) = active.plan() {
+        text += &format!("Goal: {}", plan.goal);
+        if !plan.explored.is_empty() {
+            text += &format!("\nSearched {} cells", plan.explored.len());
+        }
+        for (direction, area) in &plan.areas {
+            text += &format!("\n{direction:?}: {} cells free", area.len());
+        }
+    } else {
+        text += "This bot doesn't explain its moves";
+    }
+    text_q.single_mut().sections[0].value = text;
+}
+
+fn hide_debug_panel(mut panel_q: Query<&mut Visibility, With<DebugPanel>>) {
+    *panel_q.single_mut() = Visibility::Hidden;
+}
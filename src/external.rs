@@ -0,0 +1,367 @@
+//! A bot running as a separate program, so bots can be written in any
+//! language.
+//!
+//! The program is started with the shell when the controller takes over and
+//! stopped when it's done. As soon as the snake has moved it's sent a line
+//! of JSON on its standard input describing the board the next move will be
+//! made from, such as
+//!
+//! ```json
+//! {"tick":7,"width":64,"height":36,"wall_mode":"Solid","head":[32,18],"body":[[32,18],[31,18]],"direction":"Right","food":[40,3],"score":0,"others":[]}
+//! ```
+//!
+//! where `tick` counts the boards sent, `body` starts at the head, `others`
+//! holds the bodies of any other snakes on the board, `food` is `null` once
+//! the board is full, and `y` grows upwards. It answers with a line giving
+//! the same tick and `up`, `down`, `left` or `right`, such as
+//!
+//! ```json
+//! {"tick":7,"direction":"up"}
+//! ```
+//!
+//! The game never waits for it. If no answer has arrived by the time the
+//! move is due, or within the timeout, the snake keeps going the way it's
+//! heading, and answers for any other tick are thrown away. The program
+//! should exit once its standard input is closed. Anything it writes to its
+//! standard error shows up in the game's.
+
+use crate::{
+    controller::SnakeController,
+    game::{Direction, GameState, WallMode},
+};
+use bevy::{
+    prelude::*,
+    utils::{Duration, Instant},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Write},
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError},
+        Mutex,
+    },
+    thread,
+};
+
+/// Name the external bot goes by among the autopilot's controllers.
+pub const EXTERNAL_CONTROLLER: &str = "external";
+
+/// Steers the snake by asking an external program.
+pub struct ExternalController {
+    command: String,
+    timeout: Duration,
+    bot: Option<Bot>,
+    /// Tick of the last board sent.
+    tick: u64,
+    /// When the board awaiting an answer was sent, if there is one.
+    sent: Option<Instant>,
+}
+
+/// The running program and the threads talking to it.
+struct Bot {
+    child: Child,
+    /// Boards for the program, written to it as it reads them.
+    boards: SyncSender<String>,
+    /// Lines the program wrote, with when they arrived.
+    answers: Mutex<Receiver<(Instant, String)>>,
+}
+
+/// What the program is sent before each move.
+#[derive(Serialize)]
+struct Board<'a> {
+    tick: u64,
+    width: i32,
+    height: i32,
+    wall_mode: WallMode,
+    head: IVec2,
+    body: &'a VecDeque<IVec2>,
+    direction: Direction,
+    food: Option<IVec2>,
+    score: u32,
+    others: Vec<&'a VecDeque<IVec2>>,
+}
+
+/// What the program answers with.
+#[derive(Deserialize)]
+struct Answer {
+    tick: u64,
+    direction: String,
+}
+
+impl ExternalController {
+    /// A controller that runs `command` and gives it `timeout` to answer
+    /// each move.
+    pub fn new(command: &str, timeout: Duration) -> Self {
+        let bot = match Bot::start(command) {
+            Ok(bot) => Some(bot),
+            Err(e) => {
+                warn!("Couldn't start the bot `{command}`: {e}");
+                None
+            }
+        };
+        ExternalController {
+            command: command.to_string(),
+            timeout,
+            bot,
+            tick: 0,
+            sent: None,
+        }
+    }
+
+    /// Sends the board to the program under the next tick.
+    fn send(&mut self, game: &GameState) -> Result<(), String> {
+        let Some(bot) = &mut self.bot else {
+            return Ok(());
+        };
+        self.tick += 1;
+        let board = Board {
+            tick: self.tick,
+            width: game.occupancy().width(),
+            height: game.occupancy().height(),
+            wall_mode: game.occupancy().wall_mode(),
+            head: game.head(),
+            body: game.body(),
+            direction: game.direction(),
+            food: game.food(),
+            score: game.score(),
+            others: game.snakes()[1..].iter().map(|s| s.body()).collect(),
+        };
+        let line = serde_json::to_string(&board).unwrap();
+        match bot.boards.try_send(line) {
+            Ok(()) => {
+                self.sent = Some(Instant::now());
+                Ok(())
+            }
+            // It isn't reading its boards, so it won't answer this one either
+            Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => Err("couldn't send it the board".to_string()),
+        }
+    }
+
+    /// Takes the answer to the last board sent, if it arrived in time,
+    /// without waiting for it. Whatever else the program said is thrown away.
+    fn collect(&mut self) -> Result<Option<Direction>, String> {
+        let Some(bot) = &mut self.bot else {
+            return Ok(None);
+        };
+        let Some(sent) = self.sent.take() else {
+            return Ok(None);
+        };
+
+        let answers = bot.answers.get_mut().unwrap();
+        let mut lines = Vec::new();
+        loop {
+            match answers.try_recv() {
+                Ok(answer) => lines.push(answer),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("it stopped answering".to_string()),
+            }
+        }
+        Ok(pick_answer(lines, self.tick, sent, self.timeout))
+    }
+
+    fn give_up(&mut self, error: String) {
+        warn!("Giving up on the bot `{}`: {error}", self.command);
+        // Dropping it stops the program
+        self.bot = None;
+        self.sent = None;
+    }
+}
+
+impl SnakeController for ExternalController {
+    fn prepare(&mut self, game: &GameState) {
+        if let Err(e) = self.send(game) {
+            self.give_up(e);
+        }
+    }
+
+    fn next_direction(&mut self, _game: &GameState) -> Option<Direction> {
+        self.collect().unwrap_or_else(|e| {
+            self.give_up(e);
+            None
+        })
+    }
+}
+
+impl Bot {
+    fn start(command: &str) -> Result<Bot, String> {
+        let mut child = shell(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // Writing and reading happen on threads of their own, so the game
+        // never waits for the program to take a board or give an answer. One
+        // board can wait its turn while another is being written; any more
+        // than that and the program has stopped reading.
+        let (boards, unsent) = mpsc::sync_channel::<String>(1);
+        thread::spawn(move || {
+            for board in unsent {
+                if writeln!(stdin, "{board}")
+                    .and_then(|()| stdin.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send((Instant::now(), line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Bot {
+            child,
+            boards,
+            answers: Mutex::new(receiver),
+        })
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs `command` the way it would be run when typed into a terminal.
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+/// The direction the program answered board `tick` with, if the answer is
+/// among `answers` and arrived within `timeout` of the board being sent at
+/// `sent`. Answers to other boards are thrown away.
+fn pick_answer(
+    answers: Vec<(Instant, String)>,
+    tick: u64,
+    sent: Instant,
+    timeout: Duration,
+) -> Option<Direction> {
+    let mut choice = None;
+    for (arrived, line) in answers {
+        match parse_answer(&line) {
+            Some((answered, direction)) if answered == tick => {
+                if arrived.duration_since(sent) <= timeout {
+                    choice = Some(direction);
+                }
+            }
+            // Meant for an earlier board
+            Some(_) => {}
+            None => warn!("Ignoring the bot's answer `{}`", line.trim()),
+        }
+    }
+    choice
+}
+
+/// Reads the tick and direction from an answer, taking the direction in any
+/// case.
+fn parse_answer(line: &str) -> Option<(u64, Direction)> {
+    let answer: Answer = serde_json::from_str(line).ok()?;
+    Some((answer.tick, parse_direction(&answer.direction)?))
+}
+
+/// Reads `up`, `down`, `left` or `right` in any case.
+fn parse_direction(direction: &str) -> Option<Direction> {
+    match direction.trim().to_ascii_lowercase().as_str() {
+        "up" => Some(Direction::Up),
+        "down" => Some(Direction::Down),
+        "left" => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    /// `line` arriving `ms` milliseconds after `sent`.
+    fn answer(sent: Instant, ms: u64, line: &str) -> (Instant, String) {
+        (sent + Duration::from_millis(ms), line.to_string())
+    }
+
+    #[test]
+    fn parses_answers() {
+        assert_eq!(
+            parse_answer(r#"{"tick":7,"direction":"up"}"#),
+            Some((7, Direction::Up))
+        );
+        assert_eq!(
+            parse_answer(r#"{"direction":" Left ","tick":2}"#),
+            Some((2, Direction::Left))
+        );
+        for bad in [
+            "up",
+            r#"{"tick":7}"#,
+            r#"{"tick":-1,"direction":"up"}"#,
+            r#"{"tick":7,"direction":"north"}"#,
+            "",
+        ] {
+            assert_eq!(parse_answer(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn takes_the_answer_to_the_last_board() {
+        let sent = Instant::now();
+        let answers = vec![
+            answer(sent, 1, r#"{"tick":3,"direction":"up"}"#),
+            answer(sent, 2, r#"{"tick":4,"direction":"down"}"#),
+            answer(sent, 3, r#"{"tick":5,"direction":"left"}"#),
+        ];
+        assert_eq!(
+            pick_answer(answers, 4, sent, TIMEOUT),
+            Some(Direction::Down)
+        );
+    }
+
+    #[test]
+    fn ignores_answers_to_other_boards() {
+        let sent = Instant::now();
+        let answers = vec![
+            answer(sent, 1, r#"{"tick":3,"direction":"up"}"#),
+            answer(sent, 2, "right"),
+        ];
+        assert_eq!(pick_answer(answers, 4, sent, TIMEOUT), None);
+        assert_eq!(pick_answer(Vec::new(), 4, sent, TIMEOUT), None);
+    }
+
+    #[test]
+    fn ignores_answers_that_arrive_too_late() {
+        let sent = Instant::now();
+        let line = r#"{"tick":4,"direction":"right"}"#;
+        assert_eq!(
+            pick_answer(vec![answer(sent, 50, line)], 4, sent, TIMEOUT),
+            Some(Direction::Right)
+        );
+        assert_eq!(
+            pick_answer(vec![answer(sent, 51, line)], 4, sent, TIMEOUT),
+            None
+        );
+    }
+}
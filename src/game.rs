@@ -0,0 +1,827 @@
+//! Headless game simulation.
+//!
+//! `GameState` owns the snakes, the food and the scores and implements every
+//! rule of the game. It knows nothing about Bevy's ECS, so whole games can be
+//! played in tests and tooling without opening a window; the plugins only
+//! feed it directions and draw whatever it contains.
+//!
+//! All randomness comes from an RNG seeded when the game is created, so the
+//! same seed and the same directions always play out the same game.
+
+use bevy::math::IVec2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Right,
+        Direction::Left,
+        Direction::Up,
+        Direction::Down,
+    ];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// The direction a quarter turn anticlockwise from this one.
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    /// The direction a quarter turn clockwise from this one.
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().opposite()
+    }
+
+    pub fn as_ivec2(self) -> IVec2 {
+        match self {
+            Direction::Up => IVec2::Y,
+            Direction::Down => IVec2::NEG_Y,
+            Direction::Left => IVec2::NEG_X,
+            Direction::Right => IVec2::X,
+        }
+    }
+}
+
+/// What happens when the snake leaves the board.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WallMode {
+    /// The edges are walls and running into one kills the snake.
+    #[default]
+    Solid,
+    /// Leaving one edge re-enters the board from the opposite edge.
+    Wrap,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeathCause {
+    Wall,
+    /// Ran into its own body or another snake's.
+    Body,
+    /// Moved onto the same cell as another snake's head at the same time.
+    HeadOn,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Moved,
+    Ate,
+    /// The snake ate the last food there was room for, so the snakes now
+    /// cover the whole board.
+    Won,
+    Died(DeathCause),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cell {
+    Empty,
+    Snake,
+    Food,
+}
+
+/// Dense grid recording what covers each cell of the board, so lookups by
+/// position don't have to scan the snake.
+#[derive(Clone, Debug)]
+pub struct Occupancy {
+    width: i32,
+    height: i32,
+    wall_mode: WallMode,
+    cells: Vec<Cell>,
+}
+
+impl Occupancy {
+    fn new(width: i32, height: i32, wall_mode: WallMode) -> Self {
+        Occupancy {
+            width,
+            height,
+            wall_mode,
+            cells: vec![Cell::Empty; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn wall_mode(&self) -> WallMode {
+        self.wall_mode
+    }
+
+    pub fn in_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+
+    /// Returns what covers `pos`, or `None` if it lies off the board.
+    pub fn get(&self, pos: IVec2) -> Option<Cell> {
+        self.in_bounds(pos)
+            .then(|| self.cells[(pos.y * self.width + pos.x) as usize])
+    }
+
+    /// Whether a snake could move onto `pos` without dying.
+    pub fn is_free(&self, pos: IVec2) -> bool {
+        matches!(self.get(pos), Some(Cell::Empty | Cell::Food))
+    }
+
+    /// Returns the cell one step from `pos` in `direction`, or `None` if that
+    /// step runs into a solid wall.
+    pub fn neighbor(&self, pos: IVec2, direction: Direction) -> Option<IVec2> {
+        let next = pos + direction.as_ivec2();
+        match self.wall_mode {
+            WallMode::Solid => self.in_bounds(next).then_some(next),
+            WallMode::Wrap => Some(IVec2::new(
+                next.x.rem_euclid(self.width),
+                next.y.rem_euclid(self.height),
+            )),
+        }
+    }
+
+    /// Returns the direction of the single step from `from` to `to`, or
+    /// `None` if the two cells aren't neighbours.
+    pub fn direction_between(&self, from: IVec2, to: IVec2) -> Option<Direction> {
+        Direction::ALL
+            .into_iter()
+            .find(|&d| self.neighbor(from, d) == Some(to))
+    }
+
+    /// Fewest steps between `a` and `b` on an empty board, taking the
+    /// shortcut across the edges when they wrap.
+    pub fn distance(&self, a: IVec2, b: IVec2) -> i32 {
+        let delta = (a - b).abs();
+        match self.wall_mode {
+            WallMode::Solid => delta.x + delta.y,
+            WallMode::Wrap => {
+                delta.x.min(self.width - delta.x) + delta.y.min(self.height - delta.y)
+            }
+        }
+    }
+
+    /// Every cell nothing covers, row by row from the bottom left.
+    fn empty_cells(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|&(_, &cell)| cell == Cell::Empty)
+            .map(|(i, _)| IVec2::new(i as i32 % self.width, i as i32 / self.width))
+    }
+
+    fn set(&mut self, pos: IVec2, cell: Cell) {
+        self.cells[(pos.y * self.width + pos.x) as usize] = cell;
+    }
+}
+
+/// One of the snakes on the board.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snake {
+    /// Cells covered by the snake, head first.
+    body: VecDeque<IVec2>,
+    direction: Direction,
+    score: u32,
+    death: Option<DeathCause>,
+}
+
+impl Snake {
+    pub fn body(&self) -> &VecDeque<IVec2> {
+        &self.body
+    }
+
+    pub fn head(&self) -> IVec2 {
+        self.body[0]
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// What killed the snake, or `None` while it's still moving.
+    pub fn death(&self) -> Option<DeathCause> {
+        self.death
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.death.is_none()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GameState {
+    occupancy: Occupancy,
+    snakes: Vec<Snake>,
+    /// `None` once the snakes cover every cell.
+    food: Option<IVec2>,
+    won: bool,
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameState {
+    /// Starts a game with a horizontal snake of `length` segments in the
+    /// middle of the board, heading right.
+    pub fn new(width: i32, height: i32, wall_mode: WallMode, length: usize, seed: u64) -> Self {
+        GameState::with_snakes(width, height, wall_mode, length, 1, seed)
+    }
+
+    /// Starts a game with `snakes` horizontal snakes of `length` segments,
+    /// spread evenly up the middle of the board and all heading right.
+    pub fn with_snakes(
+        width: i32,
+        height: i32,
+        wall_mode: WallMode,
+        length: usize,
+        snakes: usize,
+        seed: u64,
+    ) -> Self {
+        let mut occupancy = Occupancy::new(width, height, wall_mode);
+        let snakes: Vec<_> = (0..snakes)
+            .map(|i| {
+                let row = (i as i32 + 1) * height / (snakes as i32 + 1);
+                let body: VecDeque<_> = (0..length as i32)
+                    .map(|j| IVec2::new(width / 2 - j, row))
+                    .collect();
+                for &pos in &body {
+                    occupancy.set(pos, Cell::Snake);
+                }
+                Snake {
+                    body,
+                    direction: Direction::Right,
+                    score: 0,
+                    death: None,
+                }
+            })
+            .collect();
+
+        let mut game = GameState {
+            occupancy,
+            snakes,
+            food: None,
+            won: false,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        };
+        game.place_food();
+        game
+    }
+
+    /// Sets up a game part way through, with `snakes` and `food` already on
+    /// the board, such as one received from a server to be drawn. Any food
+    /// placed from here on comes from an RNG seeded with `seed`.
+    pub fn with_board(
+        width: i32,
+        height: i32,
+        wall_mode: WallMode,
+        snakes: Vec<Snake>,
+        food: Option<IVec2>,
+        seed: u64,
+    ) -> Self {
+        let mut occupancy = Occupancy::new(width, height, wall_mode);
+        for &pos in snakes.iter().flat_map(|snake| &snake.body) {
+            if occupancy.in_bounds(pos) {
+                occupancy.set(pos, Cell::Snake);
+            }
+        }
+        let food = food.filter(|&food| occupancy.get(food) == Some(Cell::Empty));
+        if let Some(food) = food {
+            occupancy.set(food, Cell::Food);
+        }
+
+        GameState {
+            occupancy,
+            won: food.is_none(),
+            snakes,
+            food,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Advances the game by one move, steering the first snake in
+    /// `direction` while any others keep going the way they're heading.
+    ///
+    /// Asking the snake to reverse onto its own neck is ignored and it keeps
+    /// going in its current direction instead. Once the game has been won or
+    /// lost every further step reports the same ending and changes nothing.
+    pub fn step(&mut self, direction: Direction) -> StepOutcome {
+        self.step_all(&[direction])[0]
+    }
+
+    /// Advances the game by one move, with every snake moving at once.
+    ///
+    /// `directions[i]` steers snake `i`, and snakes without an entry keep
+    /// going the way they're heading. A snake dies if it runs into a wall or
+    /// any body, its own or another's, and two snakes moving onto the same
+    /// cell both die. The bodies of dead snakes stay on the board where they
+    /// fell.
+    ///
+    /// Returns how the move went for each snake. Snakes that died earlier
+    /// report how they died again.
+    pub fn step_all(&mut self, directions: &[Direction]) -> Vec<StepOutcome> {
+        if self.won {
+            return self
+                .snakes
+                .iter()
+                .map(|snake| snake.death.map_or(StepOutcome::Won, StepOutcome::Died))
+                .collect();
+        }
+
+        // Where each living snake's head is going
+        let targets: Vec<_> = self
+            .snakes
+            .iter_mut()
+            .enumerate()
+            .map(|(i, snake)| {
+                if snake.death.is_some() {
+                    return None;
+                }
+                if let Some(&direction) = directions.get(i) {
+                    if direction != snake.direction.opposite() {
+                        snake.direction = direction;
+                    }
+                }
+                Some(self.occupancy.neighbor(snake.head(), snake.direction))
+            })
+            .collect();
+
+        // Decide who dies against the board as it was before anyone moved, so
+        // the order the snakes are handled in doesn't matter
+        let deaths: Vec<_> = targets
+            .iter()
+            .enumerate()
+            .map(|(i, &target)| match target? {
+                None => Some(DeathCause::Wall),
+                Some(next_head) => match self.occupancy.get(next_head) {
+                    None => unreachable!("neighbors always lie on the board"),
+                    Some(Cell::Snake) => Some(DeathCause::Body),
+                    _ if targets
+                        .iter()
+                        .enumerate()
+                        .any(|(j, &other)| j != i && other == Some(Some(next_head))) =>
+                    {
+                        Some(DeathCause::HeadOn)
+                    }
+                    _ => None,
+                },
+            })
+            .collect();
+
+        let mut outcomes = Vec::with_capacity(self.snakes.len());
+        let mut ate = None;
+        for (i, snake) in self.snakes.iter_mut().enumerate() {
+            if let Some(cause) = deaths[i] {
+                snake.death = Some(cause);
+            }
+            if let Some(cause) = snake.death {
+                outcomes.push(StepOutcome::Died(cause));
+                continue;
+            }
+
+            let next_head = targets[i].flatten().unwrap();
+            snake.body.push_front(next_head);
+            if self.occupancy.get(next_head) == Some(Cell::Food) {
+                snake.score += 1;
+                ate = Some(i);
+                outcomes.push(StepOutcome::Ate);
+            } else {
+                let tail = snake.body.pop_back().unwrap();
+                self.occupancy.set(tail, Cell::Empty);
+                outcomes.push(StepOutcome::Moved);
+            }
+            self.occupancy.set(next_head, Cell::Snake);
+        }
+
+        if let Some(eater) = ate {
+            self.place_food();
+            if self.food.is_none() {
+                self.won = true;
+                outcomes[eater] = StepOutcome::Won;
+            }
+        }
+        outcomes
+    }
+
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    /// A copy of the game with snake `i` swapped to the front, so code that
+    /// looks at the first snake, such as a controller, sees it instead.
+    pub fn seen_by(&self, i: usize) -> GameState {
+        let mut game = self.clone();
+        game.snakes.swap(0, i);
+        game
+    }
+
+    /// The first snake's body. The first snake is the only one in a
+    /// single-player game, and the one the autopilot steers.
+    pub fn body(&self) -> &VecDeque<IVec2> {
+        self.snakes[0].body()
+    }
+
+    /// The first snake's head.
+    pub fn head(&self) -> IVec2 {
+        self.snakes[0].head()
+    }
+
+    /// The way the first snake is heading.
+    pub fn direction(&self) -> Direction {
+        self.snakes[0].direction()
+    }
+
+    pub fn food(&self) -> Option<IVec2> {
+        self.food
+    }
+
+    /// Whether the snakes have filled the whole board.
+    pub fn won(&self) -> bool {
+        self.won
+    }
+
+    /// Whether no snake can move any more, because the board is full or
+    /// every snake is dead.
+    pub fn is_over(&self) -> bool {
+        self.won || self.snakes.iter().all(|snake| !snake.is_alive())
+    }
+
+    /// The first snake's score.
+    pub fn score(&self) -> u32 {
+        self.snakes[0].score()
+    }
+
+    /// Foods eaten by all the snakes together.
+    pub fn food_eaten(&self) -> u32 {
+        self.snakes.iter().map(Snake::score).sum()
+    }
+
+    pub fn occupancy(&self) -> &Occupancy {
+        &self.occupancy
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A fingerprint of everything that decides how the game carries on, for
+    /// checking that two copies of a game haven't drifted apart. Unlike the
+    /// standard library's hashers it comes out the same on every platform.
+    pub fn checksum(&self) -> u64 {
+        // 64-bit FNV-1a
+        let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+        let mut write = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        for snake in &self.snakes {
+            write(&(snake.body.len() as u32).to_le_bytes());
+            for cell in &snake.body {
+                write(&cell.x.to_le_bytes());
+                write(&cell.y.to_le_bytes());
+            }
+            write(&snake.score.to_le_bytes());
+            write(&[
+                snake.direction as u8,
+                snake.death.map_or(0, |cause| cause as u8 + 1),
+            ]);
+        }
+        match self.food {
+            Some(food) => {
+                write(&[1]);
+                write(&food.x.to_le_bytes());
+                write(&food.y.to_le_bytes());
+            }
+            None => write(&[0]),
+        }
+        write(&self.rng.get_word_pos().to_le_bytes());
+        hash
+    }
+
+    /// Puts the food on a random empty cell, or takes it off the board if
+    /// there are none left.
+    ///
+    /// Picks uniformly among the empty cells rather than retrying random
+    /// positions, so it takes the same time however full the board is.
+    fn place_food(&mut self) {
+        let empty = self.occupancy.empty_cells().count();
+        self.food = (empty > 0).then(|| {
+            let index = self.rng.gen_range(0..empty);
+            self.occupancy.empty_cells().nth(index).unwrap()
+        });
+        if let Some(food) = self.food {
+            self.occupancy.set(food, Cell::Food);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hamiltonian::HamiltonianCycle;
+
+    /// A live snake covering `body`, head first, heading `direction`.
+    fn snake(body: &[(i32, i32)], direction: Direction) -> Snake {
+        Snake {
+            body: body.iter().map(|&(x, y)| IVec2::new(x, y)).collect(),
+            direction,
+            score: 0,
+            death: None,
+        }
+    }
+
+    /// A 6x6 board holding `snakes`, with the food on `food`.
+    fn board(wall_mode: WallMode, snakes: Vec<Snake>, food: (i32, i32)) -> GameState {
+        GameState::with_board(6, 6, wall_mode, snakes, Some(IVec2::from(food)), 0)
+    }
+
+    fn cells(game: &GameState) -> Vec<(i32, i32)> {
+        game.body().iter().map(|c| (c.x, c.y)).collect()
+    }
+
+    #[test]
+    fn moves_one_cell_at_a_time() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(&[(2, 2), (1, 2), (0, 2)], Direction::Right)],
+            (5, 5),
+        );
+        assert_eq!(game.step(Direction::Right), StepOutcome::Moved);
+        assert_eq!(cells(&game), [(3, 2), (2, 2), (1, 2)]);
+        assert_eq!(game.step(Direction::Up), StepOutcome::Moved);
+        assert_eq!(cells(&game), [(3, 3), (3, 2), (2, 2)]);
+        assert_eq!(game.direction(), Direction::Up);
+
+        // The cells the tail left are free again
+        assert_eq!(game.occupancy().get(IVec2::new(0, 2)), Some(Cell::Empty));
+        assert_eq!(game.occupancy().get(IVec2::new(1, 2)), Some(Cell::Empty));
+    }
+
+    #[test]
+    fn ignores_reversing_onto_the_neck() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(&[(2, 2), (1, 2), (0, 2)], Direction::Right)],
+            (5, 5),
+        );
+        assert_eq!(game.step(Direction::Left), StepOutcome::Moved);
+        assert_eq!(cells(&game), [(3, 2), (2, 2), (1, 2)]);
+        assert_eq!(game.direction(), Direction::Right);
+    }
+
+    #[test]
+    fn dies_at_solid_walls_and_wraps_otherwise() {
+        let body = [(5, 2), (4, 2), (3, 2)];
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(&body, Direction::Right)],
+            (0, 5),
+        );
+        assert_eq!(
+            game.step(Direction::Right),
+            StepOutcome::Died(DeathCause::Wall)
+        );
+        assert!(game.is_over());
+        // Nothing moves once the snake is dead
+        assert_eq!(
+            game.step(Direction::Up),
+            StepOutcome::Died(DeathCause::Wall)
+        );
+        assert_eq!(cells(&game), body);
+
+        let mut game = board(WallMode::Wrap, vec![snake(&body, Direction::Right)], (0, 5));
+        assert_eq!(game.step(Direction::Right), StepOutcome::Moved);
+        assert_eq!(cells(&game), [(0, 2), (5, 2), (4, 2)]);
+    }
+
+    #[test]
+    fn dies_on_its_own_body() {
+        // Turning back into the middle of the body
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(
+                &[(2, 2), (2, 3), (3, 3), (3, 2), (3, 1)],
+                Direction::Down,
+            )],
+            (5, 5),
+        );
+        assert_eq!(
+            game.step(Direction::Right),
+            StepOutcome::Died(DeathCause::Body)
+        );
+
+        // The tail counts too, even though it would have moved on
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(&[(1, 1), (1, 0), (0, 0), (0, 1)], Direction::Up)],
+            (5, 5),
+        );
+        assert_eq!(
+            game.step(Direction::Left),
+            StepOutcome::Died(DeathCause::Body)
+        );
+        assert_eq!(cells(&game), [(1, 1), (1, 0), (0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn grows_when_it_eats() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![snake(&[(2, 2), (1, 2), (0, 2)], Direction::Right)],
+            (3, 2),
+        );
+        assert_eq!(game.step(Direction::Right), StepOutcome::Ate);
+        assert_eq!(cells(&game), [(3, 2), (2, 2), (1, 2), (0, 2)]);
+        assert_eq!(game.score(), 1);
+
+        // The next food goes somewhere the snake isn't
+        let food = game.food().unwrap();
+        assert_ne!(food, IVec2::new(3, 2));
+        assert_eq!(game.occupancy().get(food), Some(Cell::Food));
+        assert!(!game.body().contains(&food));
+    }
+
+    /// Where the food turns up over a game played along a Hamiltonian cycle
+    /// from `seed`.
+    fn food_sequence(seed: u64) -> Vec<IVec2> {
+        let mut game = GameState::new(8, 6, WallMode::Solid, 3, seed);
+        let cycle = HamiltonianCycle::new(8, 6).unwrap();
+        let mut foods = vec![game.food().unwrap()];
+        for _ in 0..200 {
+            let direction = cycle.next_direction(&game).unwrap_or(game.direction());
+            if game.step(direction) != StepOutcome::Ate {
+                continue;
+            }
+            foods.push(game.food().unwrap());
+        }
+        foods
+    }
+
+    #[test]
+    fn same_seed_places_the_same_food() {
+        let foods = food_sequence(7);
+        assert!(foods.len() > 3);
+        assert_eq!(food_sequence(7), foods);
+        assert_ne!(food_sequence(8), foods);
+    }
+
+    /// `game` with its snakes in the opposite order.
+    fn reversed(game: &GameState) -> GameState {
+        let mut snakes = game.snakes().to_vec();
+        snakes.reverse();
+        GameState::with_board(6, 6, WallMode::Solid, snakes, game.food(), 0)
+    }
+
+    /// Steps `game` with `directions`, and a copy with the snakes the other
+    /// way round with the directions to match, and checks every snake fares
+    /// the same either way.
+    fn step_both_ways(game: &mut GameState, directions: &[Direction]) -> Vec<StepOutcome> {
+        let mut other = reversed(game);
+        let mut other_directions = directions.to_vec();
+        other_directions.reverse();
+
+        let outcomes = game.step_all(directions);
+        let mut other_outcomes = other.step_all(&other_directions);
+        other_outcomes.reverse();
+        assert_eq!(outcomes, other_outcomes);
+        assert_eq!(game.snakes().len(), other.snakes().len());
+        for (a, b) in game.snakes().iter().zip(other.snakes().iter().rev()) {
+            assert_eq!(a.body(), b.body());
+        }
+        outcomes
+    }
+
+    #[test]
+    fn heads_meeting_on_one_cell_both_die() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![
+                snake(&[(1, 2), (0, 2)], Direction::Right),
+                snake(&[(3, 2), (4, 2)], Direction::Left),
+            ],
+            (5, 5),
+        );
+        let outcomes = step_both_ways(&mut game, &[Direction::Right, Direction::Left]);
+        assert_eq!(outcomes, [StepOutcome::Died(DeathCause::HeadOn); 2]);
+        // Neither got to move onto the cell
+        assert_eq!(game.occupancy().get(IVec2::new(2, 2)), Some(Cell::Empty));
+    }
+
+    #[test]
+    fn heads_swapping_cells_both_die() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![
+                snake(&[(2, 2), (1, 2)], Direction::Right),
+                snake(&[(3, 2), (4, 2)], Direction::Left),
+            ],
+            (5, 5),
+        );
+        let outcomes = step_both_ways(&mut game, &[Direction::Right, Direction::Left]);
+        assert_eq!(outcomes, [StepOutcome::Died(DeathCause::Body); 2]);
+    }
+
+    #[test]
+    fn running_into_another_snake_kills_only_the_runner() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![
+                snake(&[(2, 2), (1, 2)], Direction::Right),
+                snake(&[(3, 3), (3, 2), (3, 1)], Direction::Up),
+            ],
+            (5, 5),
+        );
+        let outcomes = step_both_ways(&mut game, &[Direction::Right, Direction::Up]);
+        assert_eq!(
+            outcomes,
+            [StepOutcome::Died(DeathCause::Body), StepOutcome::Moved]
+        );
+        assert_eq!(game.snakes()[1].head(), IVec2::new(3, 4));
+    }
+
+    #[test]
+    fn dead_snakes_stay_on_the_board() {
+        let mut game = board(
+            WallMode::Solid,
+            vec![
+                snake(&[(5, 4), (4, 4), (3, 4)], Direction::Right),
+                snake(&[(3, 1), (2, 1)], Direction::Right),
+            ],
+            (0, 0),
+        );
+        let outcomes = step_both_ways(&mut game, &[Direction::Right, Direction::Up]);
+        assert_eq!(
+            outcomes,
+            [StepOutcome::Died(DeathCause::Wall), StepOutcome::Moved]
+        );
+        assert!(!game.is_over());
+
+        // The dead snake's body stays where it fell, in the way
+        for x in 3..6 {
+            assert_eq!(game.occupancy().get(IVec2::new(x, 4)), Some(Cell::Snake));
+        }
+        assert_eq!(
+            step_both_ways(&mut game, &[Direction::Right, Direction::Up]),
+            [StepOutcome::Died(DeathCause::Wall), StepOutcome::Moved]
+        );
+        assert_eq!(
+            step_both_ways(&mut game, &[Direction::Right, Direction::Up]),
+            [
+                StepOutcome::Died(DeathCause::Wall),
+                StepOutcome::Died(DeathCause::Body)
+            ]
+        );
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn filling_the_board_wins() {
+        for seed in 0..10 {
+            let mut game = GameState::new(4, 4, WallMode::Solid, 2, seed);
+            let cycle = HamiltonianCycle::new(4, 4).unwrap();
+            let mut outcome = StepOutcome::Moved;
+            for _ in 0..1000 {
+                let direction = cycle.next_direction(&game).unwrap_or(game.direction());
+                outcome = game.step(direction);
+                if outcome != StepOutcome::Moved && outcome != StepOutcome::Ate {
+                    break;
+                }
+            }
+            assert_eq!(outcome, StepOutcome::Won, "seed {seed}");
+            assert_eq!(game.food(), None);
+            assert_eq!(game.body().len(), 16);
+            assert!(game.won() && game.is_over());
+
+            // Nothing moves once the board is full
+            for direction in Direction::ALL {
+                assert_eq!(game.step_all(&[direction]), [StepOutcome::Won]);
+            }
+            assert_eq!(game.body().len(), 16);
+        }
+    }
+}
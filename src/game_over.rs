@@ -0,0 +1,175 @@
+use crate::{
+    actions::Action,
+    config::GameConfig,
+    high_scores::NameEntry,
+    snake::{snake_color, snake_name, Game},
+    state::AppState,
+};
+use bevy::prelude::*;
+
+pub struct GameOverPlugin;
+
+#[derive(Component, Copy, Clone)]
+enum GameOverButton {
+    Restart,
+    MainMenu,
+}
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::GameOver), setup_game_over_screen)
+            .add_systems(
+                Update,
+                (
+                    handle_game_over_button_click,
+                    // Typing a name for a new record mustn't trigger the shortcuts
+                    handle_game_over_keys.run_if(not(resource_exists::<NameEntry>)),
+                )
+                    .run_if(in_state(AppState::GameOver)),
+            );
+    }
+}
+
+fn setup_game_over_screen(mut cmd: Commands, game: Res<Game>, config: Res<GameConfig>) {
+    let text_style = TextStyle {
+        font_size: 40.,
+        ..default()
+    };
+    let restart = match config.key(Action::Restart) {
+        Some(key) => format!("Restart ({key})"),
+        None => "Restart".to_string(),
+    };
+    let snakes = game.0.snakes();
+    let (title, title_color) = if snakes.len() > 1 {
+        // Whoever ate the most wins, however long they lasted
+        let best = snakes.iter().map(|s| s.score()).max().unwrap_or(0);
+        let leaders: Vec<_> = (0..snakes.len())
+            .filter(|&i| snakes[i].score() == best)
+            .collect();
+        match leaders[..] {
+            [winner] => (
+                format!("{} Wins!", snake_name(&config, winner)),
+                snake_color(&config, winner),
+            ),
+            _ => ("Draw".to_string(), Srgba::WHITE),
+        }
+    } else if game.0.won() {
+        ("You Win!".to_string(), Srgba::rgb(1., 0.85, 0.2))
+    } else {
+        ("Game Over".to_string(), Srgba::WHITE)
+    };
+
+    cmd.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(StateScoped(AppState::GameOver))
+    .with_children(|parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    padding: UiRect::all(Val::Px(20.)),
+                    ..default()
+                },
+                background_color: Srgba::BLACK.with_alpha(0.8).into(),
+                border_radius: BorderRadius::all(Val::Px(10.)),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font_size: 60.,
+                        color: title_color.into(),
+                        ..default()
+                    },
+                ));
+                if let [snake] = snakes {
+                    parent.spawn(TextBundle::from_section(
+                        format!("Score: {}", snake.score()),
+                        text_style.clone(),
+                    ));
+                    parent.spawn(TextBundle::from_section(
+                        format!("Length: {}", snake.body().len()),
+                        text_style,
+                    ));
+                } else {
+                    for (i, snake) in snakes.iter().enumerate() {
+                        parent.spawn(TextBundle::from_section(
+                            format!(
+                                "{}: score {}, length {}",
+                                snake_name(&config, i),
+                                snake.score(),
+                                snake.body().len()
+                            ),
+                            TextStyle {
+                                color: snake_color(&config, i).into(),
+                                ..text_style.clone()
+                            },
+                        ));
+                    }
+                }
+                spawn_button(parent, &restart, GameOverButton::Restart);
+                spawn_button(parent, "Main Menu (Esc)", GameOverButton::MainMenu);
+            });
+    });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, label: &str, button: GameOverButton) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                padding: UiRect::all(Val::Px(5.)),
+                ..default()
+            },
+            background_color: Srgba::gray(0.25).into(),
+            border_radius: BorderRadius::all(Val::Px(10.)),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 30.,
+                    color: Srgba::WHITE.into(),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn handle_game_over_button_click(
+    mut next_state: ResMut<NextState<AppState>>,
+    interaction_query: Query<(&Interaction, &GameOverButton), Changed<Interaction>>,
+) {
+    for (&interaction, &button) in &interaction_query {
+        if interaction == Interaction::Pressed {
+            next_state.set(match button {
+                GameOverButton::Restart => AppState::Playing,
+                GameOverButton::MainMenu => AppState::MainMenu,
+            });
+        }
+    }
+}
+
+fn handle_game_over_keys(
+    mut next_state: ResMut<NextState<AppState>>,
+    actions: Res<ButtonInput<Action>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if actions.just_pressed(Action::Restart) {
+        next_state.set(AppState::Playing);
+    } else if input.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::MainMenu);
+    }
+}
@@ -0,0 +1,235 @@
+//! Autopilot strategy that can't trap itself.
+//!
+//! The snake follows a fixed Hamiltonian cycle, a loop through every cell of
+//! the board. As long as the body lies along the cycle in order from tail to
+//! head, every cell ahead of the head up to the tail is free, so simply
+//! following the cycle can never run into anything and eventually fills the
+//! whole board.
+//!
+//! Following the cycle alone is slow, so while the snake is short it takes
+//! shortcuts: it may jump to any neighbouring cell further along the cycle,
+//! as long as the jump doesn't overshoot the food and leaves plenty of room
+//! ahead of the head. Jumping forward keeps the body in cycle order, so the
+//! guarantee above still holds afterwards.
+
+use crate::{
+    controller::{Plan, SnakeController},
+    game::{Direction, GameState},
+    pathfinding::Pathfinding,
+};
+use bevy::math::IVec2;
+
+/// A loop visiting every cell of the board once.
+#[derive(Clone, Debug)]
+pub struct HamiltonianCycle {
+    width: i32,
+    /// Cells in the order the cycle visits them.
+    cells: Vec<IVec2>,
+    /// Position of each cell in `cells`, indexed row by row.
+    order: Vec<usize>,
+}
+
+impl HamiltonianCycle {
+    /// Builds a cycle for a new game on a board of the given size, or returns
+    /// `None` if there is none because both sides have an odd length.
+    ///
+    /// On boards with an even height the cycle runs along the snake's
+    /// starting row in the direction the snake faces, so a snake that starts
+    /// clear of the first column lies on it in order. Otherwise the snake
+    /// starts out across the cycle: the guarantee only holds once it has
+    /// followed the cycle for as many moves as it is long, leaving its
+    /// starting cells behind. Until then the cycle may lead back into its own
+    /// body, in which case `next_direction` returns `None`.
+    pub fn new(width: i32, height: i32) -> Option<Self> {
+        let cells: Vec<_> = if height % 2 == 0 {
+            // Snake left to right through every row but the first column,
+            // then come back down the first column. Mirror it if that would
+            // run through the middle row right to left.
+            let flip = (height / 2) % 2 == 1;
+            boustrophedon(width, height)
+                .into_iter()
+                .map(|c| {
+                    if flip {
+                        IVec2::new(c.x, height - 1 - c.y)
+                    } else {
+                        c
+                    }
+                })
+                .collect()
+        } else if width % 2 == 0 {
+            // The same, column by column instead, which visits the middle row
+            // left to right whichever way it's flipped
+            boustrophedon(height, width)
+                .into_iter()
+                .map(|c| IVec2::new(c.y, c.x))
+                .collect()
+        } else {
+            return None;
+        };
+
+        let mut order = vec![0; (width * height) as usize];
+        for (i, cell) in cells.iter().enumerate() {
+            order[(cell.y * width + cell.x) as usize] = i;
+        }
+        Some(HamiltonianCycle {
+            width,
+            cells,
+            order,
+        })
+    }
+
+    fn index(&self, pos: IVec2) -> usize {
+        self.order[(pos.y * self.width + pos.x) as usize]
+    }
+
+    /// The cells the cycle runs through from `from` up to and including `to`.
+    fn cells_between(&self, from: IVec2, to: IVec2) -> Vec<IVec2> {
+        let len = self.cells.len();
+        let start = self.index(from);
+        let count = (self.index(to) + len - start) % len + 1;
+        (0..count).map(|i| self.cells[(start + i) % len]).collect()
+    }
+
+    /// Picks the next move for `game`.
+    ///
+    /// Returns `None` if there is no move along the cycle, which can only
+    /// happen while the snake doesn't lie along it in order: at the start of
+    /// some games, or if someone else steered the snake off it.
+    pub fn next_direction(&self, game: &GameState) -> Option<Direction> {
+        let occupancy = game.occupancy();
+        let len = self.cells.len();
+        let head = game.head();
+        let tail = *game.body().back().unwrap();
+
+        // How far along the cycle a cell lies, counting from the tail
+        let distance = |pos: IVec2| (self.index(pos) + len - self.index(tail)) % len;
+        let head_distance = distance(head);
+        let food_distance = game.food().map(distance);
+
+        let next = self.cells[(self.index(head) + 1) % len];
+        let mut best = occupancy.is_free(next).then_some((distance(next), next));
+
+        let food_ahead = food_distance.is_some_and(|d| d > head_distance);
+        if food_ahead && game.body().len() * 2 <= len {
+            let food_distance = food_distance.unwrap();
+            for direction in Direction::ALL {
+                let Some(pos) = occupancy.neighbor(head, direction) else {
+                    continue;
+                };
+                let d = distance(pos);
+                // Leave enough free cells ahead for the snake to grow to
+                // twice its length before it could catch up with its tail
+                let room_ahead = len - 1 - d;
+                let shortcut = d > head_distance
+                    && d <= food_distance
+                    && room_ahead >= 2 * game.body().len()
+                    && occupancy.is_free(pos);
+                if shortcut && best.is_none_or(|(best_d, _)| d > best_d) {
+                    best = Some((d, pos));
+                }
+            }
+        }
+
+        best.and_then(|(_, pos)| occupancy.direction_between(head, pos))
+    }
+}
+
+/// Follows a `HamiltonianCycle` over the board. Boards without one, and
+/// snakes that don't lie along it yet because they started across it or were
+/// steered off it before this took over, are left to `Pathfinding` whenever
+/// the cycle is blocked.
+#[derive(Default)]
+pub struct CycleController {
+    cycle: Option<HamiltonianCycle>,
+    fallback: Pathfinding,
+    plan: Plan,
+}
+
+impl SnakeController for CycleController {
+    fn reset(&mut self, game: &GameState) {
+        let occupancy = game.occupancy();
+        self.cycle = HamiltonianCycle::new(occupancy.width(), occupancy.height());
+    }
+
+    fn next_direction(&mut self, game: &GameState) -> Option<Direction> {
+        let Some((cycle, direction)) = self
+            .cycle
+            .as_ref()
+            .and_then(|cycle| Some((cycle, cycle.next_direction(game)?)))
+        else {
+            let direction = self.fallback.next_direction(game);
+            self.plan = self.fallback.plan().cloned().unwrap_or_default();
+            return direction;
+        };
+
+        // Show the way round the cycle to the food, ignoring any shortcuts
+        // still to come
+        let head = game.head();
+        let next = game.occupancy().neighbor(head, direction).unwrap();
+        let food = game.food().unwrap_or(next);
+        self.plan = Plan {
+            goal: "Follow cycle",
+            path: std::iter::once(head)
+                .chain(cycle.cells_between(next, food))
+                .collect(),
+            ..Plan::default()
+        };
+        Some(direction)
+    }
+
+    fn plan(&self) -> Option<&Plan> {
+        Some(&self.plan)
+    }
+}
+
+/// A cycle that snakes back and forth through the rows, leaving out the
+/// first column, then returns down the first column.
+fn boustrophedon(width: i32, height: i32) -> Vec<IVec2> {
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        if y % 2 == 0 {
+            cells.extend((1..width).map(|x| IVec2::new(x, y)));
+        } else {
+            cells.extend((1..width).rev().map(|x| IVec2::new(x, y)));
+        }
+    }
+    cells.extend((0..height).rev().map(|y| IVec2::new(0, y)));
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{StepOutcome, WallMode};
+
+    fn play(width: i32, height: i32, wall_mode: WallMode, seed: u64) -> StepOutcome {
+        let mut game = GameState::new(width, height, wall_mode, 5, seed);
+        let cycle = HamiltonianCycle::new(width, height).unwrap();
+        loop {
+            let direction = cycle.next_direction(&game).unwrap();
+            match game.step(direction) {
+                StepOutcome::Moved | StepOutcome::Ate => {}
+                outcome => return outcome,
+            }
+        }
+    }
+
+    #[test]
+    fn fills_the_board() {
+        for seed in 0..20 {
+            assert_eq!(play(64, 36, WallMode::Solid, seed), StepOutcome::Won);
+        }
+    }
+
+    #[test]
+    fn fills_odd_and_wrapping_boards() {
+        for seed in 0..20 {
+            for (width, height) in [(8, 6), (10, 7), (7, 10), (12, 14), (31, 18)] {
+                for wall_mode in [WallMode::Solid, WallMode::Wrap] {
+                    let outcome = play(width, height, wall_mode, seed);
+                    assert_eq!(outcome, StepOutcome::Won, "{width}x{height} {wall_mode:?}");
+                }
+            }
+        }
+    }
+}
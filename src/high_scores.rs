@@ -0,0 +1,369 @@
+use crate::{
+    autopilot::{Autopilot, Hints},
+    game::WallMode,
+    replay::Playback,
+    snake::Game,
+    state::AppState,
+};
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// Number of entries kept per table.
+const TABLE_SIZE: usize = 10;
+const MAX_NAME_LENGTH: usize = 12;
+
+pub struct HighScorePlugin;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub length: usize,
+    pub width: i32,
+    pub height: i32,
+    /// Entries saved before wrap-around walls existed were all solid.
+    #[serde(default)]
+    pub wall_mode: WallMode,
+    /// Whether the autopilot or its hints were switched on at any point
+    /// during the run.
+    pub assisted: bool,
+}
+
+/// Every recorded high score, persisted to a file in the user's data
+/// directory. Runs are ranked separately per board size and wall mode, and
+/// runs the autopilot helped with never share a table with unassisted ones.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    entries: Vec<HighScoreEntry>,
+}
+
+/// Tracks whether the autopilot or its hints have been used during the
+/// current game.
+#[derive(Resource, Default)]
+struct AssistedRun(bool);
+
+/// Name being typed for a new record. Only exists until the record is saved.
+#[derive(Resource)]
+pub struct NameEntry {
+    entry: HighScoreEntry,
+}
+
+#[derive(Component)]
+struct NameEntryPanel;
+
+#[derive(Component)]
+struct NameEntryText;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_high_scores)
+            .add_systems(OnEnter(AppState::Playing), reset_assisted_run)
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                check_for_record.run_if(not(resource_exists::<Playback>)),
+            )
+            .add_systems(OnExit(AppState::GameOver), save_pending_record)
+            .add_systems(
+                Update,
+                (
+                    track_assisted_run.run_if(in_state(AppState::Playing)),
+                    (type_name, update_name_entry_text)
+                        .chain()
+                        .run_if(resource_exists::<NameEntry>),
+                ),
+            )
+            .init_resource::<AssistedRun>();
+    }
+}
+
+impl HighScores {
+    /// Best entries for a kind of board, highest score first.
+    pub fn table(
+        &self,
+        width: i32,
+        height: i32,
+        wall_mode: WallMode,
+        assisted: bool,
+    ) -> Vec<&HighScoreEntry> {
+        let mut table: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|e| {
+                (e.width, e.height, e.wall_mode, e.assisted) == (width, height, wall_mode, assisted)
+            })
+            .collect();
+        table.sort_by_key(|e| std::cmp::Reverse(e.score));
+        table.truncate(TABLE_SIZE);
+        table
+    }
+
+    fn qualifies(&self, entry: &HighScoreEntry) -> bool {
+        let table = self.table(entry.width, entry.height, entry.wall_mode, entry.assisted);
+        entry.score > 0 && (table.len() < TABLE_SIZE || table.iter().any(|e| entry.score > e.score))
+    }
+
+    fn insert(&mut self, entry: HighScoreEntry) {
+        let key = (entry.width, entry.height, entry.wall_mode, entry.assisted);
+        self.entries.push(entry);
+
+        // Drop whatever fell off the bottom of the table
+        let kept: Vec<_> = self
+            .table(key.0, key.1, key.2, key.3)
+            .into_iter()
+            .cloned()
+            .collect();
+        self.entries
+            .retain(|e| (e.width, e.height, e.wall_mode, e.assisted) != key);
+        self.entries.extend(kept);
+    }
+
+    fn save(&self) {
+        let path = high_scores_path();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| ron::ser::to_string_pretty(self, default()).map_err(|e| e.to_string()))
+            .and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            warn!("Couldn't save high scores to {}: {e}", path.display());
+        }
+    }
+}
+
+fn high_scores_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_default()
+        .join("snake")
+        .join("highscores.ron")
+}
+
+fn load_high_scores(mut cmd: Commands) {
+    let path = high_scores_path();
+    let high_scores = match fs::read_to_string(&path) {
+        Ok(contents) => ron::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Ignoring invalid high score file {}: {e}", path.display());
+            HighScores::default()
+        }),
+        Err(_) => HighScores::default(),
+    };
+    cmd.insert_resource(high_scores);
+}
+
+fn reset_assisted_run(mut assisted: ResMut<AssistedRun>) {
+    assisted.0 = false;
+}
+
+fn track_assisted_run(
+    mut assisted: ResMut<AssistedRun>,
+    autopilot: Res<Autopilot>,
+    hints: Res<Hints>,
+) {
+    if (autopilot.0 || hints.0) && !assisted.0 {
+        assisted.0 = true;
+    }
+}
+
+fn check_for_record(
+    mut cmd: Commands,
+    assisted: Res<AssistedRun>,
+    game: Res<Game>,
+    high_scores: Res<HighScores>,
+) {
+    // Scores from games against other snakes aren't comparable with solo runs
+    if game.0.snakes().len() > 1 {
+        return;
+    }
+
+    let occupancy = game.0.occupancy();
+    let entry = HighScoreEntry {
+        name: String::new(),
+        score: game.0.score(),
+        length: game.0.body().len(),
+        width: occupancy.width(),
+        height: occupancy.height(),
+        wall_mode: occupancy.wall_mode(),
+        assisted: assisted.0,
+    };
+    if !high_scores.qualifies(&entry) {
+        return;
+    }
+
+    let title = if entry.assisted {
+        "New assisted high score!"
+    } else {
+        "New high score!"
+    };
+    cmd.insert_resource(NameEntry { entry });
+
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert((NameEntryPanel, StateScoped(AppState::GameOver)))
+    .with_children(|parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(10.)),
+                    ..default()
+                },
+                background_color: Srgba::BLACK.with_alpha(0.8).into(),
+                border_radius: BorderRadius::all(Val::Px(10.)),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font_size: 40.,
+                        ..default()
+                    },
+                ));
+                parent
+                    .spawn(TextBundle::from_sections([
+                        TextSection::new(
+                            "Name: ",
+                            TextStyle {
+                                font_size: 30.,
+                                ..default()
+                            },
+                        ),
+                        TextSection::new(
+                            "_",
+                            TextStyle {
+                                font_size: 30.,
+                                ..default()
+                            },
+                        ),
+                    ]))
+                    .insert(NameEntryText);
+                parent.spawn(TextBundle::from_section(
+                    "Press Enter to save",
+                    TextStyle {
+                        font_size: 20.,
+                        color: Srgba::gray(0.7).into(),
+                        ..default()
+                    },
+                ));
+            });
+    });
+}
+
+fn type_name(
+    mut cmd: Commands,
+    mut ev_keyboard: EventReader<KeyboardInput>,
+    mut high_scores: ResMut<HighScores>,
+    mut name_entry: ResMut<NameEntry>,
+    panel_q: Query<Entity, With<NameEntryPanel>>,
+) {
+    for ev in ev_keyboard.read() {
+        if ev.state != ButtonState::Pressed {
+            continue;
+        }
+
+        let name = &mut name_entry.entry.name;
+        match &ev.logical_key {
+            Key::Enter => {
+                save_record(&mut cmd, &mut high_scores, &name_entry);
+                for panel in &panel_q {
+                    cmd.entity(panel).despawn_recursive();
+                }
+                return;
+            }
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Space if !name.is_empty() => type_chars(name, [' ']),
+            Key::Character(c) => type_chars(name, c.chars().filter(|c| c.is_alphanumeric())),
+            _ => {}
+        }
+    }
+}
+
+/// Adds `typed` to the end of `name`, one character at a time, for as long
+/// as there's room left in it.
+fn type_chars(name: &mut String, typed: impl IntoIterator<Item = char>) {
+    for c in typed {
+        if name.chars().count() >= MAX_NAME_LENGTH {
+            break;
+        }
+        name.push(c);
+    }
+}
+
+fn update_name_entry_text(
+    name_entry: Res<NameEntry>,
+    mut query: Query<&mut Text, With<NameEntryText>>,
+) {
+    if !name_entry.is_changed() {
+        return;
+    }
+
+    for mut text in &mut query {
+        text.sections[1].value = format!("{}_", name_entry.entry.name);
+    }
+}
+
+/// Keeps a record whose name was never confirmed, so leaving the game-over
+/// screen early doesn't lose it.
+fn save_pending_record(
+    mut cmd: Commands,
+    mut high_scores: ResMut<HighScores>,
+    name_entry: Option<Res<NameEntry>>,
+) {
+    if let Some(name_entry) = name_entry {
+        save_record(&mut cmd, &mut high_scores, &name_entry);
+    }
+}
+
+fn save_record(cmd: &mut Commands, high_scores: &mut HighScores, name_entry: &NameEntry) {
+    let mut entry = name_entry.entry.clone();
+    let name = entry.name.trim();
+    entry.name = if name.is_empty() {
+        "Player".to_string()
+    } else {
+        name.to_string()
+    };
+
+    high_scores.insert(entry);
+    high_scores.save();
+    cmd.remove_resource::<NameEntry>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_hold_at_most_max_name_length_characters() {
+        let mut name = String::new();
+        type_chars(&mut name, "Ziggy".chars());
+        // Several characters at once, as an input method can commit them
+        type_chars(&mut name, "Stardust1972".chars());
+        assert_eq!(name, "ZiggyStardus");
+        type_chars(&mut name, "x".chars());
+        assert_eq!(name.chars().count(), MAX_NAME_LENGTH);
+
+        // Counted in characters, not bytes
+        let mut name = String::new();
+        type_chars(&mut name, "Åsa Öberg Ünal".chars());
+        assert_eq!(name, "Åsa Öberg Ün");
+    }
+}
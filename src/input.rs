@@ -1,40 +1,218 @@
+use crate::{
+    actions::{Action, PlayerAction},
+    autopilot::AutopilotSet,
+    config::{ControlScheme, GameConfig},
+    game::Direction,
+    replay::Playback,
+    snake::{Game, SnakeMoveEvent, SnakeMoveTimerTickSet},
+};
 use bevy::prelude::*;
+use std::collections::VecDeque;
+
+/// Most turns that can be waiting for the snake to make them.
+const MAX_QUEUED_TURNS: usize = 3;
 
 pub struct InputPlugin;
 
-#[derive(Resource)]
-pub struct CurrentDirection(pub Dir2);
+/// Direction a snake is steered in on its next move.
+#[derive(Component)]
+pub struct NextDirection(pub Direction);
+
+/// Turns the player has asked for that the snake hasn't made yet, oldest
+/// first. The snake makes one per move, so quick key sequences like a U-turn
+/// aren't lost between moves.
+#[derive(Component, Default)]
+pub struct InputQueue(pub VecDeque<Direction>);
 
-#[derive(Resource)]
-pub struct NextDirection(pub Dir2);
+/// The person at this machine steering a snake, counting from 0, whose
+/// controls it follows.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Player(pub usize);
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, change_head_direction)
-            .insert_resource(CurrentDirection(Dir2::X))
-            .insert_resource(NextDirection(Dir2::X));
+        app.add_systems(
+            Update,
+            (
+                change_head_direction.before(SnakeMoveTimerTickSet),
+                take_queued_turn
+                    .after(SnakeMoveTimerTickSet)
+                    .before(AutopilotSet),
+            )
+                .run_if(resource_exists::<Game>)
+                .run_if(not(resource_exists::<Playback>)),
+        );
     }
 }
 
 fn change_head_direction(
-    current_direction: Res<CurrentDirection>,
-    mut next_direction: ResMut<NextDirection>,
-    input: Res<ButtonInput<KeyCode>>,
+    mut snake_q: Query<(&Player, &mut InputQueue, &NextDirection)>,
+    actions: Res<ButtonInput<PlayerAction>>,
+    config: Res<GameConfig>,
 ) {
-    let desired = if input.just_pressed(KeyCode::KeyW) {
-        Dir2::Y
-    } else if input.just_pressed(KeyCode::KeyA) {
-        Dir2::NEG_X
-    } else if input.just_pressed(KeyCode::KeyS) {
-        Dir2::NEG_Y
-    } else if input.just_pressed(KeyCode::KeyD) {
-        Dir2::X
+    for (&Player(player), mut queue, next_direction) in &mut snake_q {
+        let heading = queue.heading(next_direction.0);
+        let desired = match config.controls[player].control_scheme {
+            ControlScheme::Absolute => absolute_turn(&actions, player),
+            ControlScheme::Relative => relative_turn(&actions, player, heading),
+        };
+        if let Some(desired) = desired {
+            queue.push(desired, next_direction.0);
+        }
+    }
+}
+
+impl InputQueue {
+    /// Where a snake about to head `next` will be heading once it has made
+    /// every queued turn.
+    pub fn heading(&self, next: Direction) -> Direction {
+        self.0.back().copied().unwrap_or(next)
+    }
+
+    /// Queues a turn to `desired` for a snake about to head `next`. Turns
+    /// that wouldn't change the heading by then, 180's and turns beyond
+    /// `MAX_QUEUED_TURNS` are dropped. Returns whether the turn was queued.
+    pub fn push(&mut self, desired: Direction, next: Direction) -> bool {
+        let heading = self.heading(next);
+        let queued =
+            desired != heading && desired != heading.opposite() && self.0.len() < MAX_QUEUED_TURNS;
+        if queued {
+            self.0.push_back(desired);
+        }
+        queued
+    }
+}
+
+fn absolute_turn(actions: &ButtonInput<PlayerAction>, player: usize) -> Option<Direction> {
+    let pressed = |action| actions.just_pressed(PlayerAction { player, action });
+    if pressed(Action::Up) {
+        Some(Direction::Up)
+    } else if pressed(Action::Left) {
+        Some(Direction::Left)
+    } else if pressed(Action::Down) {
+        Some(Direction::Down)
+    } else if pressed(Action::Right) {
+        Some(Direction::Right)
+    } else {
+        None
+    }
+}
+
+/// Left and Right turn the snake from `heading`. Up and Down do nothing.
+fn relative_turn(
+    actions: &ButtonInput<PlayerAction>,
+    player: usize,
+    heading: Direction,
+) -> Option<Direction> {
+    let pressed = |action| actions.just_pressed(PlayerAction { player, action });
+    if pressed(Action::Left) {
+        Some(heading.turn_left())
+    } else if pressed(Action::Right) {
+        Some(heading.turn_right())
     } else {
+        None
+    }
+}
+
+fn take_queued_turn(
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut snake_q: Query<(&mut InputQueue, &mut NextDirection)>,
+) {
+    if ev_move.is_empty() {
         return;
-    };
+    }
+    ev_move.clear();
+
+    for (mut queue, mut next_direction) in &mut snake_q {
+        if let Some(direction) = queue.0.pop_front() {
+            next_direction.0 = direction;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction::*;
+
+    #[test]
+    fn queues_turns_from_the_last_queued_heading() {
+        let mut queue = InputQueue::default();
+        // Up then Left makes a U-turn over two moves
+        assert!(queue.push(Up, Right));
+        assert!(queue.push(Left, Right));
+        assert_eq!(queue.0, [Up, Left]);
+        assert_eq!(queue.heading(Right), Left);
+
+        // Right is only a reversal of the last queued turn, Down of the first
+        assert!(!queue.push(Right, Right));
+        assert!(queue.push(Down, Right));
+        assert_eq!(queue.0, [Up, Left, Down]);
+    }
+
+    #[test]
+    fn skips_turns_that_change_nothing() {
+        let mut queue = InputQueue::default();
+        assert!(!queue.push(Right, Right));
+        assert!(!queue.push(Left, Right));
+        assert!(queue.0.is_empty());
+
+        assert!(queue.push(Up, Right));
+        // Pressing the same way twice queues it once
+        assert!(!queue.push(Up, Right));
+        assert_eq!(queue.0, [Up]);
+    }
+
+    #[test]
+    fn caps_the_queued_turns() {
+        let mut queue = InputQueue::default();
+        for (i, direction) in [Up, Left, Down, Right, Up].into_iter().enumerate() {
+            assert_eq!(queue.push(direction, Right), i < MAX_QUEUED_TURNS);
+        }
+        assert_eq!(queue.0.len(), MAX_QUEUED_TURNS);
+        assert_eq!(queue.0, [Up, Left, Down]);
+    }
+
+    /// Input with `action` just pressed by the first player.
+    fn pressing(action: Action) -> ButtonInput<PlayerAction> {
+        let mut actions = ButtonInput::default();
+        actions.press(PlayerAction { player: 0, action });
+        actions
+    }
+
+    #[test]
+    fn turns_relative_to_the_heading() {
+        let turns = [
+            (Up, Left, Right),
+            (Left, Down, Up),
+            (Down, Right, Left),
+            (Right, Up, Down),
+        ];
+        for (heading, left, right) in turns {
+            assert_eq!(
+                relative_turn(&pressing(Action::Left), 0, heading),
+                Some(left)
+            );
+            assert_eq!(
+                relative_turn(&pressing(Action::Right), 0, heading),
+                Some(right)
+            );
+            for action in [Action::Up, Action::Down] {
+                assert_eq!(relative_turn(&pressing(action), 0, heading), None);
+            }
+        }
+        // Another player's input doesn't count
+        assert_eq!(relative_turn(&pressing(Action::Left), 1, Up), None);
+    }
 
-    // Don't allow 180's
-    if desired != -current_direction.0 {
-        next_direction.0 = desired;
+    #[test]
+    fn turns_relative_to_the_last_queued_turn() {
+        let mut queue = InputQueue::default();
+        queue.push(Up, Right);
+        // Turning left again from Up, not from Right
+        let turn = relative_turn(&pressing(Action::Left), 0, queue.heading(Right));
+        assert_eq!(turn, Some(Left));
+        assert!(queue.push(turn.unwrap(), Right));
+        assert_eq!(queue.0, [Up, Left]);
     }
 }
@@ -0,0 +1,31 @@
+//! Snake, with an optional autopilot.
+//!
+//! The rules of the game live in `game` and the bots steering the autopilot
+//! and rival snakes in `controller`, `pathfinding`, `hamiltonian`,
+//! `external` and `rival`, none of which need Bevy's ECS. The rest are the plugins the `snake` binary builds its app from.
+
+pub mod actions;
+pub mod autopilot;
+pub mod camera;
+pub mod cli;
+pub mod config;
+pub mod constants;
+pub mod controller;
+pub mod debug_overlay;
+pub mod external;
+pub mod food;
+pub mod game;
+pub mod game_over;
+pub mod hamiltonian;
+pub mod high_scores;
+pub mod input;
+pub mod menu;
+pub mod netplay;
+pub mod pathfinding;
+pub mod pause;
+pub mod replay;
+pub mod rival;
+pub mod score;
+pub mod snake;
+pub mod spectator;
+pub mod state;
@@ -1,46 +1,131 @@
-use autopilot::{AutopilotPlugin, AutopilotSet};
 use bevy::prelude::*;
-use camera::CameraPlugin;
-use collision::{CollisionPlugin, CollisionSet};
-use food::{EatSet, FoodPlugin};
-use input::InputPlugin;
-use pause::PausePlugin;
-use score::ScorePlugin;
-use snake::{SnakeMoveSet, SnakeMoveTimerTickSet, SnakePlugin};
+use clap::Parser;
+use snake::{
+    actions::ActionPlugin,
+    autopilot::{AutopilotPlugin, AutopilotSet},
+    camera::CameraPlugin,
+    cli::Cli,
+    config::{self, ConfigPath, GameConfig},
+    debug_overlay::DebugOverlayPlugin,
+    food::{EatSet, FoodPlugin},
+    game_over::GameOverPlugin,
+    high_scores::HighScorePlugin,
+    input::InputPlugin,
+    menu::MenuPlugin,
+    netplay::{Netplay, NetplayPlugin},
+    pause::PausePlugin,
+    replay::{Replay, ReplayPlugin},
+    score::ScorePlugin,
+    snake::{SnakeMoveSet, SnakeMoveTimerTickSet, SnakePlugin},
+    spectator::{Spectator, SpectatorPlugin},
+    state::AppState,
+};
+use std::process::ExitCode;
 
-mod autopilot;
-mod camera;
-mod collision;
-mod constants;
-mod food;
-mod input;
-mod pause;
-mod score;
-mod snake;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-fn main() {
-    App::new()
+    let replay = match cli.replay.as_deref().map(Replay::load).transpose() {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Couldn't load replay: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let spectator = match cli.watch.as_deref().map(Spectator::connect).transpose() {
+        Ok(spectator) => spectator,
+        Err(e) => {
+            eprintln!("Couldn't connect to the server: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let config_path = config::config_path(&cli);
+    let config = match (&replay, spectator) {
+        // Replays are still watched with the player's own controls
+        (Some(replay), _) => {
+            let mut config = GameConfig {
+                controls: GameConfig::load(&config_path).controls,
+                ..replay.game_config()
+            };
+            config.sanitize();
+            (config, None)
+        }
+        // The server's games are drawn with its board, but nothing here steers
+        (None, Some((spectator, server_config))) => {
+            let mut config = GameConfig {
+                controls: GameConfig::load(&config_path).controls,
+                autopilot: false,
+                hints: false,
+                ..server_config
+            };
+            config.sanitize();
+            (config, Some(spectator))
+        }
+        (None, None) => (GameConfig::from_cli(&cli), None),
+    };
+    let (config, spectator) = config;
+    let netplay = match (cli.host, &cli.join) {
+        (Some(port), _) => Netplay::host(port).map(Some),
+        (None, Some(address)) => Netplay::join(address).map(Some),
+        (None, None) => Ok(None),
+    };
+    let netplay = match netplay {
+        Ok(netplay) => netplay,
+        Err(e) => {
+            eprintln!("Couldn't start the network game: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // A replay or a game being watched starts straight away rather than
+    // waiting in the menu, and a network game waits for the other player
+    // instead
+    let initial_state = if replay.is_some() || spectator.is_some() {
+        AppState::Playing
+    } else if netplay.is_some() {
+        AppState::Lobby
+    } else {
+        AppState::default()
+    };
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .add_plugins((
-            DefaultPlugins,
+            ActionPlugin,
             AutopilotPlugin,
             CameraPlugin,
-            CollisionPlugin,
+            DebugOverlayPlugin,
             FoodPlugin,
+            GameOverPlugin,
+            HighScorePlugin,
             InputPlugin,
+            MenuPlugin,
+            NetplayPlugin,
             PausePlugin,
+            ReplayPlugin {
+                record: cli.record,
+                replay,
+            },
             ScorePlugin,
             SnakePlugin,
+            SpectatorPlugin,
         ))
+        .insert_resource(config)
+        .insert_resource(ConfigPath(config_path))
+        .insert_state(initial_state)
+        .enable_state_scoped_entities::<AppState>()
         .configure_sets(
             Update,
-            (
-                SnakeMoveTimerTickSet,
-                AutopilotSet,
-                CollisionSet,
-                EatSet,
-                SnakeMoveSet,
-            )
-                .chain(),
-        )
-        .run();
+            (SnakeMoveTimerTickSet, AutopilotSet, SnakeMoveSet, EatSet)
+                .chain()
+                .run_if(in_state(AppState::Playing)),
+        );
+    if let Some(netplay) = netplay {
+        app.insert_resource(netplay);
+    }
+    if let Some(spectator) = spectator {
+        app.insert_resource(spectator);
+    }
+    app.run();
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,917 @@
+use crate::{
+    actions::{Action, RawInput},
+    config::{ConfigPath, ControlScheme, GameConfig, PlayerControls, RivalConfig, SpeedCurve},
+    constants::*,
+    controller::ControllerRegistry,
+    game::WallMode,
+    high_scores::{HighScoreEntry, HighScores},
+    rival::Difficulty,
+    state::AppState,
+};
+use bevy::{app::AppExit, prelude::*, ui::FocusPolicy};
+use std::mem::discriminant;
+
+pub struct MenuPlugin;
+
+#[derive(Component, Copy, Clone)]
+enum MenuButton {
+    Play,
+    Settings,
+    Controls,
+    HighScores,
+    Quit,
+    Back,
+    ResetBindings,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Setting {
+    GridWidth,
+    GridHeight,
+    WallMode,
+    SnakeLength,
+    InitialSpeed,
+    SpeedCurve,
+    SpeedUp,
+    MaxSpeed,
+    Autopilot,
+    AutopilotController,
+    Hints,
+    /// Shown on the main menu rather than with the other settings, like the
+    /// rivals.
+    Players,
+    Rivals,
+    /// Difficulty of every rival.
+    RivalDifficulty,
+}
+
+/// Steps `setting` down (-1) or up (+1) when clicked.
+#[derive(Component)]
+struct SettingButton {
+    setting: Setting,
+    step: i32,
+}
+
+#[derive(Component)]
+struct SettingValueText(Setting);
+
+/// Options on the controls screen that aren't bindings.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ControlsOption {
+    /// Which player's controls are shown.
+    Player,
+    ControlScheme,
+}
+
+/// Steps `option` down (-1) or up (+1) when clicked.
+#[derive(Component)]
+struct ControlsButton {
+    option: ControlsOption,
+    step: i32,
+}
+
+#[derive(Component)]
+struct ControlsValueText(ControlsOption);
+
+/// The player whose controls the controls screen shows, counting from 0.
+#[derive(Resource, Default)]
+struct ControlsPlayer(usize);
+
+/// Rebinds `action` to the next key pressed, or the next gamepad input if
+/// `gamepad`, when clicked.
+#[derive(Component, Copy, Clone)]
+struct RebindButton {
+    action: Action,
+    gamepad: bool,
+}
+
+#[derive(Component)]
+struct BindingsText(Action);
+
+/// The rebind button that is waiting for input, if any.
+#[derive(Resource, Default)]
+struct Rebinding(Option<RebindButton>);
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::MainMenu), setup_main_menu)
+            .add_systems(OnEnter(AppState::Settings), setup_settings_menu)
+            .add_systems(OnEnter(AppState::Controls), setup_controls_menu)
+            .add_systems(OnExit(AppState::Controls), cancel_rebinding)
+            .add_systems(OnEnter(AppState::HighScores), setup_high_scores_menu)
+            .add_systems(
+                Update,
+                (
+                    handle_menu_button_click,
+                    (handle_setting_button_click, update_setting_values)
+                        .chain()
+                        .run_if(in_state(AppState::MainMenu).or_else(in_state(AppState::Settings))),
+                    (
+                        handle_controls_button_click,
+                        handle_rebind_button_click,
+                        capture_binding,
+                        (update_controls_values, update_bindings_text),
+                    )
+                        .chain()
+                        .run_if(in_state(AppState::Controls)),
+                    // Escape cancels a rebinding before it leaves the screen
+                    leave_submenu_on_key.before(capture_binding).run_if(
+                        in_state(AppState::Settings)
+                            .or_else(in_state(AppState::Controls))
+                            .or_else(in_state(AppState::HighScores)),
+                    ),
+                ),
+            )
+            .init_resource::<Rebinding>()
+            .init_resource::<ControlsPlayer>();
+    }
+}
+
+impl Setting {
+    const ALL: [Setting; 11] = [
+        Setting::GridWidth,
+        Setting::GridHeight,
+        Setting::WallMode,
+        Setting::SnakeLength,
+        Setting::InitialSpeed,
+        Setting::SpeedCurve,
+        Setting::SpeedUp,
+        Setting::MaxSpeed,
+        Setting::Autopilot,
+        Setting::AutopilotController,
+        Setting::Hints,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::GridWidth => "Grid width",
+            Setting::GridHeight => "Grid height",
+            Setting::WallMode => "Walls",
+            Setting::SnakeLength => "Starting length",
+            Setting::InitialSpeed => "Starting speed",
+            Setting::SpeedCurve => "Speed curve",
+            Setting::SpeedUp => "Speed-up",
+            Setting::MaxSpeed => "Max speed",
+            Setting::Autopilot => "Autopilot",
+            Setting::AutopilotController => "Autopilot bot",
+            Setting::Hints => "Hints",
+            Setting::Players => "Players",
+            Setting::Rivals => "Rivals",
+            Setting::RivalDifficulty => "Rival level",
+        }
+    }
+
+    fn value(self, config: &GameConfig, registry: &ControllerRegistry) -> String {
+        match self {
+            Setting::GridWidth => config.grid_width.to_string(),
+            Setting::GridHeight => config.grid_height.to_string(),
+            Setting::WallMode => wall_mode_name(config.wall_mode).to_string(),
+            Setting::SnakeLength => config.snake_length.to_string(),
+            Setting::InitialSpeed => format!("{}", config.initial_speed),
+            Setting::SpeedCurve => match config.speed_curve {
+                SpeedCurve::Multiply(_) => "Multiply",
+                SpeedCurve::Linear(_) => "Linear",
+                SpeedCurve::Stepped { .. } => "Stepped",
+            }
+            .to_string(),
+            Setting::SpeedUp => match config.speed_curve {
+                SpeedCurve::Multiply(factor) => format!("x{factor:.2}"),
+                SpeedCurve::Linear(increase) => format!("+{increase:.1}"),
+                SpeedCurve::Stepped { every, increase } => format!("+{increase:.1}/{every}"),
+            },
+            Setting::MaxSpeed => format!("{}", config.max_speed),
+            Setting::Autopilot => if config.autopilot { "On" } else { "Off" }.to_string(),
+            Setting::AutopilotController => {
+                registry.get(&config.autopilot_controller).label.to_string()
+            }
+            Setting::Hints => if config.hints { "On" } else { "Off" }.to_string(),
+            Setting::Players => config.players.to_string(),
+            Setting::Rivals => config.rivals.len().to_string(),
+            Setting::RivalDifficulty => match config.rivals.first() {
+                Some(rival) => format!("{:?}", rival.difficulty),
+                None => "-".to_string(),
+            },
+        }
+    }
+
+    fn adjust(self, config: &mut GameConfig, registry: &ControllerRegistry, step: i32) {
+        // Work in tenths and hundredths so repeated clicks don't accumulate
+        // float error
+        let stepped = |value: f32, scale: f32, min: f32, max: f32| {
+            (((value * scale).round() + step as f32) / scale).clamp(min, max)
+        };
+
+        match self {
+            Setting::GridWidth => config.grid_width = (config.grid_width + 2 * step).clamp(8, 128),
+            Setting::GridHeight => {
+                config.grid_height = (config.grid_height + 2 * step).clamp(6, 72)
+            }
+            Setting::WallMode => {
+                config.wall_mode = match config.wall_mode {
+                    WallMode::Solid => WallMode::Wrap,
+                    WallMode::Wrap => WallMode::Solid,
+                }
+            }
+            Setting::SnakeLength => {
+                config.snake_length = (config.snake_length as i32 + step).max(1) as usize
+            }
+            Setting::InitialSpeed => {
+                config.initial_speed = (config.initial_speed + step as f32).clamp(1., 30.)
+            }
+            Setting::SpeedCurve => {
+                let curves = [
+                    SpeedCurve::Multiply(SPEED_UP),
+                    SpeedCurve::Linear(0.5),
+                    SpeedCurve::Stepped {
+                        every: 5,
+                        increase: 1.,
+                    },
+                ];
+                let kind = discriminant(&config.speed_curve);
+                let current = curves.iter().position(|c| discriminant(c) == kind).unwrap();
+                config.speed_curve = curves[(current as i32 + step).rem_euclid(3) as usize];
+            }
+            Setting::SpeedUp => match &mut config.speed_curve {
+                SpeedCurve::Multiply(factor) => *factor = stepped(*factor, 100., 1., 1.2),
+                SpeedCurve::Linear(increase) => *increase = stepped(*increase, 10., 0., 2.),
+                SpeedCurve::Stepped { increase, .. } => *increase = stepped(*increase, 10., 0., 5.),
+            },
+            Setting::MaxSpeed => {
+                config.max_speed = (config.max_speed + 5. * step as f32).clamp(5., 60.)
+            }
+            Setting::Autopilot => config.autopilot = !config.autopilot,
+            Setting::AutopilotController => {
+                let entry = registry.step(&config.autopilot_controller, step);
+                config.autopilot_controller = entry.name.to_string();
+            }
+            Setting::Hints => config.hints = !config.hints,
+            Setting::Players => config.players = (config.players as i32 + step).max(1) as usize,
+            Setting::Rivals => {
+                if step > 0 {
+                    // New rivals are as tough as the ones already there
+                    let difficulty = config.rivals.last().map(|r| r.difficulty);
+                    let rival =
+                        RivalConfig::new(config.rivals.len(), difficulty.unwrap_or_default());
+                    config.rivals.push(rival);
+                } else {
+                    config.rivals.pop();
+                }
+            }
+            Setting::RivalDifficulty => {
+                if let Some(first) = config.rivals.first() {
+                    let all = Difficulty::ALL;
+                    let current = all.iter().position(|&d| d == first.difficulty).unwrap();
+                    let difficulty = all[(current as i32 + step).clamp(0, 2) as usize];
+                    for rival in &mut config.rivals {
+                        rival.difficulty = difficulty;
+                    }
+                }
+            }
+        }
+        config.sanitize();
+    }
+}
+
+impl ControlsOption {
+    fn label(self) -> &'static str {
+        match self {
+            ControlsOption::Player => "Player",
+            ControlsOption::ControlScheme => "Steering",
+        }
+    }
+
+    fn value(self, config: &GameConfig, player: usize) -> String {
+        match self {
+            ControlsOption::Player => (player + 1).to_string(),
+            ControlsOption::ControlScheme => {
+                format!("{:?}", config.controls[player].control_scheme)
+            }
+        }
+    }
+}
+
+fn wall_mode_name(wall_mode: WallMode) -> &'static str {
+    match wall_mode {
+        WallMode::Solid => "Solid",
+        WallMode::Wrap => "Wrap",
+    }
+}
+
+fn setup_main_menu(mut cmd: Commands, config: Res<GameConfig>, registry: Res<ControllerRegistry>) {
+    spawn_menu_screen(&mut cmd, AppState::MainMenu, |parent| {
+        spawn_title(parent, "Snake");
+        spawn_menu_button(parent, "Play", MenuButton::Play);
+        for setting in [Setting::Players, Setting::Rivals, Setting::RivalDifficulty] {
+            spawn_setting_row(parent, setting, &config, &registry);
+        }
+        spawn_menu_button(parent, "Settings", MenuButton::Settings);
+        spawn_menu_button(parent, "High Scores", MenuButton::HighScores);
+        spawn_menu_button(parent, "Quit", MenuButton::Quit);
+    });
+}
+
+fn setup_settings_menu(
+    mut cmd: Commands,
+    config: Res<GameConfig>,
+    registry: Res<ControllerRegistry>,
+) {
+    spawn_menu_screen(&mut cmd, AppState::Settings, |parent| {
+        spawn_title(parent, "Settings");
+        for setting in Setting::ALL {
+            spawn_setting_row(parent, setting, &config, &registry);
+        }
+        spawn_menu_button_row(
+            parent,
+            &[
+                ("Controls", MenuButton::Controls),
+                ("Back", MenuButton::Back),
+            ],
+        );
+    });
+}
+
+fn setup_controls_menu(mut cmd: Commands, config: Res<GameConfig>, player: Res<ControlsPlayer>) {
+    spawn_menu_screen(&mut cmd, AppState::Controls, |parent| {
+        spawn_title(parent, "Controls");
+        parent.spawn(TextBundle::from_section(
+            "Click Key or Pad, then press what to bind. Esc cancels.",
+            TextStyle {
+                font_size: 24.,
+                color: Srgba::gray(0.7).into(),
+                ..default()
+            },
+        ));
+        for option in [ControlsOption::Player, ControlsOption::ControlScheme] {
+            spawn_stepper_row(
+                parent,
+                option.label(),
+                option.value(&config, player.0),
+                |step| ControlsButton { option, step },
+                ControlsValueText(option),
+            );
+        }
+        // Two columns of actions, so they all fit on the screen
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(20.),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                for column in Action::ALL.chunks(Action::ALL.len().div_ceil(2)) {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                row_gap: Val::Px(10.),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for &action in column {
+                                spawn_bindings_row(parent, action, &config.controls[player.0]);
+                            }
+                        });
+                }
+            });
+        spawn_menu_button_row(
+            parent,
+            &[
+                ("Reset", MenuButton::ResetBindings),
+                ("Back", MenuButton::Back),
+            ],
+        );
+    });
+}
+
+fn setup_high_scores_menu(
+    mut cmd: Commands,
+    config: Res<GameConfig>,
+    high_scores: Res<HighScores>,
+) {
+    let (width, height, wall_mode) = (config.grid_width, config.grid_height, config.wall_mode);
+    let walls = wall_mode_name(wall_mode).to_lowercase();
+
+    spawn_menu_screen(&mut cmd, AppState::HighScores, |parent| {
+        spawn_title(parent, "High Scores");
+        parent.spawn(TextBundle::from_section(
+            format!("{width}x{height} board, {walls} walls"),
+            TextStyle {
+                font_size: 30.,
+                ..default()
+            },
+        ));
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(40.),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                spawn_high_score_table(
+                    parent,
+                    "Players",
+                    high_scores.table(width, height, wall_mode, false),
+                );
+                spawn_high_score_table(
+                    parent,
+                    "Autopilot-assisted",
+                    high_scores.table(width, height, wall_mode, true),
+                );
+            });
+        spawn_menu_button(parent, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_high_score_table(parent: &mut ChildBuilder, title: &str, table: Vec<&HighScoreEntry>) {
+    let text_style = TextStyle {
+        font_size: 24.,
+        ..default()
+    };
+
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(300.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 30.,
+                    ..default()
+                },
+            ));
+            if table.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    "No scores yet",
+                    text_style.clone(),
+                ));
+            }
+            for (rank, entry) in table.iter().enumerate() {
+                parent.spawn(TextBundle::from_section(
+                    format!(
+                        "{}. {} - {} (length {})",
+                        rank + 1,
+                        entry.name,
+                        entry.score,
+                        entry.length
+                    ),
+                    text_style.clone(),
+                ));
+            }
+        });
+}
+
+/// Spawns a rounded panel centred over a dimmed screen that lives as long as
+/// `state` does.
+fn spawn_menu_screen(
+    cmd: &mut Commands,
+    state: AppState,
+    spawn_contents: impl FnOnce(&mut ChildBuilder),
+) {
+    cmd.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        background_color: Srgba::BLACK.with_alpha(0.5).into(),
+        // Keep clicks from reaching the HUD buttons underneath
+        focus_policy: FocusPolicy::Block,
+        ..default()
+    })
+    .insert(StateScoped(state))
+    .with_children(|parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    padding: UiRect::all(Val::Px(20.)),
+                    ..default()
+                },
+                background_color: Srgba::BLACK.with_alpha(0.8).into(),
+                border_radius: BorderRadius::all(Val::Px(10.)),
+                ..default()
+            })
+            .with_children(spawn_contents);
+    });
+}
+
+fn spawn_title(parent: &mut ChildBuilder, title: &str) {
+    parent.spawn(TextBundle::from_section(
+        title,
+        TextStyle {
+            font_size: 60.,
+            ..default()
+        },
+    ));
+}
+
+fn spawn_button(parent: &mut ChildBuilder, label: &str, marker: impl Component) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                min_width: Val::Px(40.),
+                justify_content: JustifyContent::Center,
+                padding: UiRect::all(Val::Px(5.)),
+                ..default()
+            },
+            background_color: Srgba::gray(0.25).into(),
+            border_radius: BorderRadius::all(Val::Px(10.)),
+            ..default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 30.,
+                    color: Srgba::WHITE.into(),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn spawn_menu_button(parent: &mut ChildBuilder, label: &str, button: MenuButton) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(200.),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| spawn_button(parent, label, button));
+}
+
+/// Spawns menu buttons side by side.
+fn spawn_menu_button_row(parent: &mut ChildBuilder, buttons: &[(&str, MenuButton)]) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                column_gap: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for &(label, button) in buttons {
+                spawn_menu_button(parent, label, button);
+            }
+        });
+}
+
+fn spawn_setting_row(
+    parent: &mut ChildBuilder,
+    setting: Setting,
+    config: &GameConfig,
+    registry: &ControllerRegistry,
+) {
+    spawn_stepper_row(
+        parent,
+        setting.label(),
+        setting.value(config, registry),
+        |step| SettingButton { setting, step },
+        SettingValueText(setting),
+    );
+}
+
+/// Adds a row with `label` and `value` between buttons that step the value
+/// down and up. `button` makes the marker for the button stepping by `step`.
+fn spawn_stepper_row<B: Component>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    value: String,
+    button: impl Fn(i32) -> B,
+    value_marker: impl Component,
+) {
+    let text_style = TextStyle {
+        font_size: 30.,
+        ..default()
+    };
+
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(450.),
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(label, text_style.clone()).with_style(Style {
+                    flex_grow: 1.,
+                    ..default()
+                }),
+            );
+            spawn_button(parent, "<", button(-1));
+            parent
+                .spawn(
+                    TextBundle::from_section(value, text_style)
+                        .with_text_justify(JustifyText::Center)
+                        .with_style(Style {
+                            width: Val::Px(70.),
+                            ..default()
+                        }),
+                )
+                .insert(value_marker);
+            spawn_button(parent, ">", button(1));
+        });
+}
+
+fn spawn_bindings_row(parent: &mut ChildBuilder, action: Action, controls: &PlayerControls) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(600.),
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    action.label(),
+                    TextStyle {
+                        font_size: 30.,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(150.),
+                    ..default()
+                }),
+            );
+            parent
+                .spawn(
+                    TextBundle::from_section(
+                        bindings_label(action, controls, None),
+                        TextStyle {
+                            font_size: 24.,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        flex_grow: 1.,
+                        flex_basis: Val::Px(0.),
+                        ..default()
+                    }),
+                )
+                .insert(BindingsText(action));
+            spawn_button(
+                parent,
+                "Key",
+                RebindButton {
+                    action,
+                    gamepad: false,
+                },
+            );
+            spawn_button(
+                parent,
+                "Pad",
+                RebindButton {
+                    action,
+                    gamepad: true,
+                },
+            );
+        });
+}
+
+/// Lists what `action` is bound to, or asks for input if it's the one being
+/// rebound.
+fn bindings_label(
+    action: Action,
+    controls: &PlayerControls,
+    rebinding: Option<RebindButton>,
+) -> String {
+    match rebinding {
+        Some(button) if button.action == action && button.gamepad => {
+            "Press a gamepad button or push a stick...".to_string()
+        }
+        Some(button) if button.action == action => "Press a key...".to_string(),
+        _ => {
+            let bindings: Vec<_> = controls
+                .bindings
+                .get(action)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            if bindings.is_empty() {
+                "Not bound".to_string()
+            } else {
+                bindings.join(", ")
+            }
+        }
+    }
+}
+
+/// The screen the Back button and Escape lead to from `state`.
+fn parent_screen(state: AppState) -> AppState {
+    match state {
+        AppState::Controls => AppState::Settings,
+        _ => AppState::MainMenu,
+    }
+}
+
+fn handle_menu_button_click(
+    mut ev_exit: EventWriter<AppExit>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut config: ResMut<GameConfig>,
+    state: Res<State<AppState>>,
+    config_path: Res<ConfigPath>,
+    interaction_query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (&interaction, &button) in &interaction_query {
+        if interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            MenuButton::Play => next_state.set(AppState::Playing),
+            MenuButton::Settings => next_state.set(AppState::Settings),
+            MenuButton::Controls => next_state.set(AppState::Controls),
+            MenuButton::HighScores => next_state.set(AppState::HighScores),
+            MenuButton::Quit => {
+                ev_exit.send(AppExit::Success);
+            }
+            MenuButton::Back => next_state.set(parent_screen(*state.get())),
+            MenuButton::ResetBindings => {
+                config.controls = (0..MAX_PLAYERS).map(PlayerControls::for_player).collect();
+                save_controls(&config_path, &config);
+            }
+        }
+    }
+}
+
+fn handle_setting_button_click(
+    mut config: ResMut<GameConfig>,
+    registry: Res<ControllerRegistry>,
+    interaction_query: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+) {
+    for (&interaction, button) in &interaction_query {
+        if interaction == Interaction::Pressed {
+            button.setting.adjust(&mut config, &registry, button.step);
+        }
+    }
+}
+
+fn update_setting_values(
+    config: Res<GameConfig>,
+    registry: Res<ControllerRegistry>,
+    mut query: Query<(&mut Text, &SettingValueText)>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    for (mut text, value) in &mut query {
+        text.sections[0].value = value.0.value(&config, &registry);
+    }
+}
+
+fn handle_controls_button_click(
+    mut player: ResMut<ControlsPlayer>,
+    mut config: ResMut<GameConfig>,
+    config_path: Res<ConfigPath>,
+    interaction_query: Query<(&Interaction, &ControlsButton), Changed<Interaction>>,
+) {
+    for (&interaction, button) in &interaction_query {
+        if interaction != Interaction::Pressed {
+            continue;
+        }
+        match button.option {
+            ControlsOption::Player => {
+                player.0 = (player.0 as i32 + button.step).rem_euclid(MAX_PLAYERS as i32) as usize
+            }
+            ControlsOption::ControlScheme => {
+                let controls = &mut config.controls[player.0];
+                controls.control_scheme = match controls.control_scheme {
+                    ControlScheme::Absolute => ControlScheme::Relative,
+                    ControlScheme::Relative => ControlScheme::Absolute,
+                };
+                // Controls are kept between runs, unlike the other settings
+                save_controls(&config_path, &config);
+            }
+        }
+    }
+}
+
+fn update_controls_values(
+    config: Res<GameConfig>,
+    player: Res<ControlsPlayer>,
+    mut query: Query<(&mut Text, &ControlsValueText)>,
+) {
+    if !config.is_changed() && !player.is_changed() {
+        return;
+    }
+
+    for (mut text, value) in &mut query {
+        text.sections[0].value = value.0.value(&config, player.0);
+    }
+}
+
+fn handle_rebind_button_click(
+    mut rebinding: ResMut<Rebinding>,
+    interaction_query: Query<(&Interaction, &RebindButton), Changed<Interaction>>,
+) {
+    for (&interaction, &button) in &interaction_query {
+        if interaction == Interaction::Pressed {
+            rebinding.0 = Some(button);
+        }
+    }
+}
+
+/// Binds whatever the player presses to the action waiting for it.
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut config: ResMut<GameConfig>,
+    config_path: Res<ConfigPath>,
+    player: Res<ControlsPlayer>,
+    keys: Res<ButtonInput<KeyCode>>,
+    input: RawInput,
+) {
+    let Some(button) = rebinding.0 else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        rebinding.0 = None;
+        return;
+    }
+
+    let binding = if button.gamepad {
+        input.new_gamepad_input()
+    } else {
+        input.new_key()
+    };
+    if let Some(binding) = binding {
+        // Everyone shares the keyboard, but each player has their own gamepad
+        if !binding.is_gamepad() {
+            for controls in &mut config.controls {
+                controls.bindings.unbind(binding);
+            }
+        }
+        config.controls[player.0]
+            .bindings
+            .bind(button.action, binding);
+        save_controls(&config_path, &config);
+        rebinding.0 = None;
+    }
+}
+
+fn save_controls(config_path: &ConfigPath, config: &GameConfig) {
+    if let Err(e) = GameConfig::save_controls(&config_path.0, config) {
+        warn!("Couldn't save controls to {}: {e}", config_path.0.display());
+    }
+}
+
+fn update_bindings_text(
+    config: Res<GameConfig>,
+    player: Res<ControlsPlayer>,
+    rebinding: Res<Rebinding>,
+    mut query: Query<(&mut Text, &BindingsText)>,
+) {
+    if !config.is_changed() && !player.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    let controls = &config.controls[player.0];
+    for (mut text, bindings) in &mut query {
+        text.sections[0].value = bindings_label(bindings.0, controls, rebinding.0);
+    }
+}
+
+fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+fn leave_submenu_on_key(
+    mut next_state: ResMut<NextState<AppState>>,
+    state: Res<State<AppState>>,
+    rebinding: Res<Rebinding>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Escape) && rebinding.0.is_none() {
+        next_state.set(parent_screen(*state.get()));
+    }
+}
@@ -0,0 +1,1101 @@
+//! Two-player games over the network.
+//!
+//! Both sides run the whole game themselves and only send each other the
+//! directions their player steers in, relying on `GameState` playing out the
+//! same way from the same seed and moves. A move isn't made until the other
+//! side's direction for it has arrived, so the two games stay in step however
+//! the network behaves. Turns are held back by a few moves, the input delay,
+//! so that they've usually arrived by the time they're needed.
+//!
+//! Messages are JSON over UDP. Moves are sent again until the other side says
+//! it has them, and every so often both sides swap checksums of their game to
+//! catch them drifting apart.
+
+use crate::{
+    autopilot::AutopilotSet,
+    config::GameConfig,
+    game::Direction,
+    input::{InputQueue, NextDirection, Player},
+    pause::IsPaused,
+    replay::Moves,
+    snake::{
+        Game, SetupSnakeSet, SnakeId, SnakeMoveEvent, SnakeMoveSet, SnakeMoveTimer,
+        SnakeMoveTimerTickSet,
+    },
+    state::AppState,
+};
+use bevy::{prelude::*, utils::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Changes whenever the messages or the rules of the game do, so that
+/// different versions of the game don't try to play together.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Largest message that fits in a UDP datagram.
+const MAX_MESSAGE_SIZE: usize = 65_507;
+
+/// How often messages the other side hasn't answered are sent again.
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The other side is given up on after this long without hearing from it.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Moves between checksums of the game.
+const CHECKSUM_INTERVAL: u32 = 20;
+
+/// How long a move has to be held up for the other side before the wait is
+/// pointed out.
+const STALL_NOTICE: Duration = Duration::from_millis(500);
+
+pub struct NetplayPlugin;
+
+/// A network game with one other player, from the lobby until either side
+/// leaves.
+#[derive(Resource)]
+pub struct Netplay {
+    socket: UdpSocket,
+    role: Role,
+    phase: Phase,
+    /// The snake steered from this side. The host's snake comes first.
+    local: usize,
+    input_delay: u32,
+    /// Seed of the first game. Each game after it takes the next one.
+    seed: u64,
+    /// The game being played, counting from 0, once the first has started.
+    game: Option<u32>,
+    /// The latest game the other side has started.
+    peer_game: u32,
+    /// Moves of the current game, any game the other side is still finishing
+    /// and any it has already started.
+    games: BTreeMap<u32, GameMoves>,
+    /// Moves made so far in the current game.
+    tick: u32,
+    /// Settings to go back to once the session is over and its last game is
+    /// off the screen.
+    own_config: Option<GameConfig>,
+    /// When the other side was last heard from, in real time.
+    last_heard: Duration,
+    /// When the next move started waiting for the other side, if it is.
+    stalled_since: Option<Duration>,
+    resend_timer: Timer,
+}
+
+enum Role {
+    Host { port: u16 },
+    Join { address: SocketAddr },
+}
+
+enum Phase {
+    /// Waiting for someone to join, or for the host to let us in.
+    Lobby,
+    Connected {
+        peer: SocketAddr,
+    },
+    /// The session is over, for the reason given.
+    Ended(String),
+}
+
+/// Both sides' moves in one game.
+#[derive(Default)]
+struct GameMoves {
+    /// This side's moves. The `i`th is made on move `i + input_delay`.
+    local: Vec<Direction>,
+    /// The other side's moves, as many as have arrived.
+    remote: Vec<Direction>,
+    /// How many of `local` the other side has said it has.
+    acked: usize,
+    /// Checksums of this side's game, by the number of moves made.
+    checksums: BTreeMap<u32, u64>,
+    /// Checksums from the other side that haven't been compared yet.
+    remote_checksums: BTreeMap<u32, u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// Asks the host to join its game.
+    Join { version: u32 },
+    /// Lets the joining player in, with the settings every game is played
+    /// with.
+    Welcome { config: GameConfig, seed: u64 },
+    /// Turns the joining player away.
+    Refuse { reason: String },
+    /// The sender's moves in `game` from its `first` onwards, how many of the
+    /// receiver's moves it has and its latest checksum.
+    Moves {
+        game: u32,
+        first: usize,
+        moves: Moves,
+        received: usize,
+        checksum: Option<(u32, u64)>,
+    },
+    /// The session is over, for the reason given.
+    Leave { reason: String },
+}
+
+/// What handling a message changed about the session.
+enum Change {
+    Started,
+    Ended,
+}
+
+#[derive(Component)]
+struct LobbyText;
+
+#[derive(Component)]
+struct NetplayPanel;
+
+#[derive(Component)]
+struct NetplayText;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_netplay_ui.run_if(resource_exists::<Netplay>))
+            .add_systems(OnEnter(AppState::Lobby), setup_lobby_screen)
+            .add_systems(
+                OnEnter(AppState::MainMenu),
+                (leave_session, restore_own_config)
+                    .chain()
+                    .run_if(resource_exists::<Netplay>),
+            )
+            .add_systems(
+                OnExit(AppState::GameOver),
+                restore_own_config.run_if(resource_exists::<Netplay>),
+            )
+            .add_systems(
+                OnEnter(AppState::Playing),
+                (
+                    start_game.before(SetupSnakeSet),
+                    take_seats.after(SetupSnakeSet),
+                )
+                    .run_if(online),
+            )
+            .add_systems(
+                Update,
+                (
+                    (receive_messages, send_messages)
+                        .chain()
+                        .before(SnakeMoveTimerTickSet),
+                    (
+                        wait_for_peer.before(SnakeMoveTimerTickSet),
+                        exchange_moves.after(AutopilotSet).before(SnakeMoveSet),
+                        check_sync.after(SnakeMoveSet),
+                    )
+                        .run_if(online)
+                        .run_if(in_state(AppState::Playing)),
+                    cancel_lobby.run_if(in_state(AppState::Lobby)),
+                    update_netplay_ui,
+                )
+                    .run_if(resource_exists::<Netplay>),
+            );
+    }
+}
+
+impl Netplay {
+    /// Opens a game on `port` for another player to join.
+    pub fn host(port: u16) -> Result<Netplay, String> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).map_err(|e| e.to_string())?;
+        let port = socket.local_addr().map_err(|e| e.to_string())?.port();
+        Netplay::new(socket, Role::Host { port }, 0)
+    }
+
+    /// Asks to join the game hosted at `address`.
+    pub fn join(address: &str) -> Result<Netplay, String> {
+        let address = address
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("no address found for `{address}`"))?;
+        let any: SocketAddr = if address.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(any).map_err(|e| e.to_string())?;
+        Netplay::new(socket, Role::Join { address }, 1)
+    }
+
+    fn new(socket: UdpSocket, role: Role, local: usize) -> Result<Netplay, String> {
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Netplay {
+            socket,
+            role,
+            phase: Phase::Lobby,
+            local,
+            input_delay: 0,
+            seed: 0,
+            game: None,
+            peer_game: 0,
+            games: BTreeMap::new(),
+            tick: 0,
+            own_config: None,
+            last_heard: Duration::ZERO,
+            stalled_since: None,
+            resend_timer: Timer::new(RESEND_INTERVAL, TimerMode::Repeating),
+        })
+    }
+
+    fn peer(&self) -> Option<SocketAddr> {
+        match self.phase {
+            Phase::Connected { peer } => Some(peer),
+            _ => None,
+        }
+    }
+
+    /// The snake steered from the other side.
+    fn remote(&self) -> usize {
+        1 - self.local
+    }
+
+    fn moves(&self) -> Option<&GameMoves> {
+        self.games.get(&self.game?)
+    }
+
+    fn moves_mut(&mut self) -> Option<&mut GameMoves> {
+        self.games.get_mut(&self.game?)
+    }
+
+    /// Whether the other side's direction for the next move has arrived.
+    fn ready(&self) -> bool {
+        let Some(game) = self.game else {
+            return false;
+        };
+        if self.peer_game < game {
+            return false;
+        }
+        match (self.tick as usize).checked_sub(self.input_delay as usize) {
+            // Nobody's turns have come through yet
+            None => true,
+            Some(i) => self.moves().is_some_and(|moves| moves.remote.len() > i),
+        }
+    }
+
+    fn send(&self, to: SocketAddr, message: &Message) {
+        let bytes = serde_json::to_vec(message).expect("messages always serialize");
+        if let Err(e) = self.socket.send_to(&bytes, to) {
+            warn!("Couldn't send to {to}: {e}");
+        }
+    }
+
+    /// Sends the other side whichever of our moves it hasn't got yet, along
+    /// with how many of its moves have arrived.
+    fn send_moves(&self) {
+        let (Some(peer), Some(current)) = (self.peer(), self.game) else {
+            return;
+        };
+        for (&game, moves) in self.games.range(..=current) {
+            let first = moves.acked.min(moves.local.len());
+            let message = Message::Moves {
+                game,
+                first,
+                moves: Moves(moves.local[first..].to_vec()),
+                received: moves.remote.len(),
+                checksum: moves.checksums.last_key_value().map(|(&t, &c)| (t, c)),
+            };
+            self.send(peer, &message);
+        }
+    }
+
+    fn welcome(&self, config: &GameConfig) -> Message {
+        Message::Welcome {
+            // The other side steers with its own controls, and has no use for
+            // a bot while playing online
+            config: GameConfig {
+                controls: Vec::new(),
+                bot: None,
+                ..config.clone()
+            },
+            seed: self.seed,
+        }
+    }
+
+    fn handle(
+        &mut self,
+        message: Message,
+        from: SocketAddr,
+        config: &mut GameConfig,
+        now: Duration,
+    ) -> Option<Change> {
+        if self.peer() == Some(from) {
+            self.last_heard = now;
+        }
+
+        match message {
+            Message::Join { version } => self.handle_join(version, from, config, now),
+            Message::Welcome {
+                config: host_config,
+                seed,
+            } => {
+                if !matches!((&self.role, &self.phase), (Role::Join { .. }, Phase::Lobby)) {
+                    return None;
+                }
+                self.own_config = Some(config.clone());
+                *config = GameConfig {
+                    controls: config.controls.clone(),
+                    ..host_config
+                };
+                config.sanitize();
+                self.input_delay = config.input_delay;
+                self.seed = seed;
+                self.phase = Phase::Connected { peer: from };
+                self.last_heard = now;
+                Some(Change::Started)
+            }
+            Message::Refuse { reason } => {
+                if !matches!((&self.role, &self.phase), (Role::Join { .. }, Phase::Lobby)) {
+                    return None;
+                }
+                self.end(reason);
+                Some(Change::Ended)
+            }
+            Message::Moves {
+                game,
+                first,
+                moves,
+                received,
+                checksum,
+            } => {
+                if self.peer() != Some(from) {
+                    return None;
+                }
+                let tick = self.receive_moves(game, first, moves.0, received, checksum)?;
+                self.desync(tick);
+                Some(Change::Ended)
+            }
+            Message::Leave { reason } => {
+                if self.peer() != Some(from) {
+                    return None;
+                }
+                self.end(reason);
+                Some(Change::Ended)
+            }
+        }
+    }
+
+    fn handle_join(
+        &mut self,
+        version: u32,
+        from: SocketAddr,
+        config: &mut GameConfig,
+        now: Duration,
+    ) -> Option<Change> {
+        if !matches!(self.role, Role::Host { .. }) {
+            return None;
+        }
+        if version != PROTOCOL_VERSION {
+            let reason = "The host is running a different version of the game".to_string();
+            self.send(from, &Message::Refuse { reason });
+            return None;
+        }
+
+        match self.phase {
+            Phase::Lobby => {
+                self.own_config = Some(config.clone());
+                // One player on each side
+                config.players = 2;
+                config.sanitize();
+                self.input_delay = config.input_delay;
+                self.seed = config.seed.unwrap_or_else(rand::random);
+                self.phase = Phase::Connected { peer: from };
+                self.last_heard = now;
+                self.send(from, &self.welcome(config));
+                Some(Change::Started)
+            }
+            // Our welcome got lost on the way
+            Phase::Connected { peer } if peer == from => {
+                self.send(from, &self.welcome(config));
+                None
+            }
+            _ => {
+                let reason = "The host isn't taking any more players".to_string();
+                self.send(from, &Message::Refuse { reason });
+                None
+            }
+        }
+    }
+
+    /// Stores the other side's moves and checksum for `game`, returning the
+    /// move the two games were found to differ at, if they do.
+    fn receive_moves(
+        &mut self,
+        game: u32,
+        first: usize,
+        moves: Vec<Direction>,
+        received: usize,
+        checksum: Option<(u32, u64)>,
+    ) -> Option<u32> {
+        // Held up on the way from a game the other side has finished with
+        if game < self.peer_game {
+            return None;
+        }
+        self.peer_game = game;
+
+        let entry = self.games.entry(game).or_default();
+        if let Some(new) = entry
+            .remote
+            .len()
+            .checked_sub(first)
+            .and_then(|seen| moves.get(seen..))
+        {
+            entry.remote.extend_from_slice(new);
+        }
+        entry.acked = entry.acked.max(received);
+        if let Some((tick, checksum)) = checksum {
+            entry.remote_checksums.insert(tick, checksum);
+        }
+        let mismatch = entry.compare_checksums();
+
+        // Neither side needs the moves of a game both have moved on from
+        if let Some(current) = self.game {
+            let oldest = current.min(self.peer_game);
+            self.games.retain(|&game, _| game >= oldest);
+        }
+        mismatch
+    }
+
+    /// Ends the session after finding the two games differ at move `tick`.
+    fn desync(&mut self, tick: u32) {
+        let reason = format!("Out of sync with the other player at move {tick}");
+        error!("{reason}");
+        self.leave(reason.clone(), reason);
+    }
+
+    /// Ends the session, telling the other side why if it's still there.
+    fn leave(&mut self, reason: String, reason_for_peer: String) {
+        if let Some(peer) = self.peer() {
+            let message = Message::Leave {
+                reason: reason_for_peer,
+            };
+            self.send(peer, &message);
+        }
+        self.end(reason);
+    }
+
+    /// Ends the session. The session's settings stay in place while its last
+    /// game is still being played or shown, until `restore_config`.
+    fn end(&mut self, reason: String) {
+        self.phase = Phase::Ended(reason);
+    }
+
+    /// Goes back to this side's own settings once the session is over.
+    fn restore_config(&mut self, config: &mut GameConfig) {
+        if !matches!(self.phase, Phase::Ended(_)) {
+            return;
+        }
+        if let Some(own_config) = self.own_config.take() {
+            *config = own_config;
+        }
+    }
+
+    /// Moves on to the next game of the session, returning its number.
+    fn start_game(&mut self) -> u32 {
+        let game = self.game.map_or(0, |game| game + 1);
+        self.game = Some(game);
+        self.tick = 0;
+        self.stalled_since = None;
+        // The other side may have started it already and sent its first moves
+        self.games.entry(game).or_default();
+        game
+    }
+}
+
+impl GameMoves {
+    /// Compares every checksum both sides have for the same move, returning
+    /// the first move they differ at.
+    fn compare_checksums(&mut self) -> Option<u32> {
+        let mut mismatch = None;
+        self.remote_checksums
+            .retain(|tick, remote| match self.checksums.get(tick) {
+                Some(local) => {
+                    if local != remote {
+                        mismatch = mismatch.or(Some(*tick));
+                    }
+                    false
+                }
+                None => true,
+            });
+        mismatch
+    }
+}
+
+/// Whether a game is being played over the network with another player.
+pub fn online(netplay: Option<Res<Netplay>>) -> bool {
+    netplay.is_some_and(|netplay| netplay.peer().is_some())
+}
+
+fn receive_messages(
+    mut netplay: ResMut<Netplay>,
+    mut config: ResMut<GameConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+    state: Res<State<AppState>>,
+    time: Res<Time<Real>>,
+) {
+    let mut buffer = [0; MAX_MESSAGE_SIZE];
+    loop {
+        let (len, from) = match netplay.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            // Such as the other side's port being closed, which shows up
+            // anyway once it stops answering
+            Err(_) => break,
+        };
+        let Ok(message) = serde_json::from_slice(&buffer[..len]) else {
+            warn!("Ignoring a message from {from} that couldn't be read");
+            continue;
+        };
+
+        match netplay.handle(message, from, &mut config, time.elapsed()) {
+            Some(Change::Started) => next_state.set(AppState::Playing),
+            Some(Change::Ended) if *state.get() == AppState::Playing => {
+                next_state.set(AppState::GameOver)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends the moves the other side is missing every so often, which also lets
+/// it know we're still here, and gives up on it if it's gone quiet.
+fn send_messages(
+    mut netplay: ResMut<Netplay>,
+    mut next_state: ResMut<NextState<AppState>>,
+    state: Res<State<AppState>>,
+    time: Res<Time<Real>>,
+) {
+    if !netplay.resend_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    match (&netplay.phase, &netplay.role) {
+        (Phase::Lobby, &Role::Join { address }) => {
+            let message = Message::Join {
+                version: PROTOCOL_VERSION,
+            };
+            netplay.send(address, &message);
+        }
+        (Phase::Connected { .. }, _) => {
+            if time.elapsed() - netplay.last_heard > PEER_TIMEOUT {
+                let reason = "Lost the connection to the other player".to_string();
+                warn!("{reason}");
+                netplay.end(reason);
+                if *state.get() == AppState::Playing {
+                    next_state.set(AppState::GameOver);
+                }
+            } else {
+                netplay.send_moves();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn leave_session(mut netplay: ResMut<Netplay>) {
+    if matches!(netplay.phase, Phase::Ended(_)) {
+        return;
+    }
+    netplay.leave(
+        "Left the online game".to_string(),
+        "The other player left".to_string(),
+    );
+}
+
+/// Puts this side's own settings back once a finished session's last game is
+/// no longer on the screen, since the snakes are named and coloured from the
+/// settings while it is.
+fn restore_own_config(mut netplay: ResMut<Netplay>, mut config: ResMut<GameConfig>) {
+    netplay.restore_config(&mut config);
+}
+
+/// Moves on to the next game of the session, played with the next seed so
+/// that the food doesn't fall the same way every time.
+fn start_game(mut netplay: ResMut<Netplay>, mut config: ResMut<GameConfig>) {
+    let game = netplay.start_game();
+    config.seed = Some(netplay.seed.wrapping_add(game as u64));
+}
+
+/// Hands the other side's snake over to the network and ours to the player
+/// at this machine.
+fn take_seats(
+    mut cmd: Commands,
+    netplay: Res<Netplay>,
+    snake_q: Query<(Entity, &SnakeId), With<InputQueue>>,
+) {
+    for (entity, &SnakeId(id)) in &snake_q {
+        if id == netplay.local {
+            cmd.entity(entity).insert(Player(0));
+        } else {
+            cmd.entity(entity).remove::<(InputQueue, Player)>();
+        }
+    }
+}
+
+/// Holds the next move back until the other side's direction for it has
+/// arrived.
+fn wait_for_peer(
+    mut netplay: ResMut<Netplay>,
+    mut timer: ResMut<SnakeMoveTimer>,
+    is_paused: Res<IsPaused>,
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+) {
+    let due = !is_paused.0 && time.delta() >= timer.0.remaining();
+    if due && !netplay.ready() {
+        timer.0.pause();
+        netplay.stalled_since.get_or_insert(real_time.elapsed());
+    } else {
+        timer.0.unpause();
+        netplay.stalled_since = None;
+    }
+}
+
+/// Sends the direction the player has asked for and steers both players'
+/// snakes the way they asked `input_delay` moves ago.
+fn exchange_moves(
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut netplay: ResMut<Netplay>,
+    mut snake_q: Query<(&SnakeId, &mut NextDirection)>,
+    game: Res<Game>,
+) {
+    if ev_move.is_empty() {
+        return;
+    }
+    ev_move.clear();
+
+    let (local, remote) = (netplay.local, netplay.remote());
+    let tick = netplay.tick as usize;
+    let delay = netplay.input_delay as usize;
+    let Some(asked) = snake_q
+        .iter()
+        .find(|(id, _)| id.0 == local)
+        .map(|(_, next_direction)| next_direction.0)
+    else {
+        return;
+    };
+    let Some(moves) = netplay.moves_mut() else {
+        return;
+    };
+    moves.local.push(asked);
+
+    let directions = match tick.checked_sub(delay) {
+        // `wait_for_peer` held the move back until the other side's arrived
+        Some(i) => [moves.local[i], moves.remote[i]],
+        // Nobody's turns have come through yet, so both snakes go straight
+        None => [local, remote].map(|id| game.0.snakes()[id].direction()),
+    };
+    for (&SnakeId(id), mut next_direction) in &mut snake_q {
+        if id == local {
+            next_direction.0 = directions[0];
+        } else if id == remote {
+            next_direction.0 = directions[1];
+        }
+    }
+    netplay.send_moves();
+}
+
+/// Counts the move just made, takes a checksum of the game every so often
+/// and compares it with the other side's.
+fn check_sync(
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut netplay: ResMut<Netplay>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut snake_q: Query<(&SnakeId, &mut NextDirection)>,
+    game: Res<Game>,
+) {
+    if ev_move.is_empty() {
+        return;
+    }
+    ev_move.clear();
+
+    netplay.tick += 1;
+    let (local, tick) = (netplay.local, netplay.tick);
+    let Some(moves) = netplay.moves_mut() else {
+        return;
+    };
+
+    // Back to the way the player last asked to go, for the next move to send
+    if let Some(&asked) = moves.local.last() {
+        for (&SnakeId(id), mut next_direction) in &mut snake_q {
+            if id == local {
+                next_direction.0 = asked;
+            }
+        }
+    }
+
+    if tick % CHECKSUM_INTERVAL != 0 {
+        return;
+    }
+    moves.checksums.insert(tick, game.0.checksum());
+    if let Some(tick) = moves.compare_checksums() {
+        netplay.desync(tick);
+        next_state.set(AppState::GameOver);
+    }
+}
+
+fn cancel_lobby(mut next_state: ResMut<NextState<AppState>>, input: Res<ButtonInput<KeyCode>>) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+fn setup_lobby_screen(mut cmd: Commands) {
+    cmd.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(StateScoped(AppState::Lobby))
+    .with_children(|parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.),
+                    padding: UiRect::all(Val::Px(20.)),
+                    ..default()
+                },
+                background_color: Srgba::BLACK.with_alpha(0.8).into(),
+                border_radius: BorderRadius::all(Val::Px(10.)),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Online Game",
+                    TextStyle {
+                        font_size: 60.,
+                        ..default()
+                    },
+                ));
+                parent
+                    .spawn(TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 30.,
+                            ..default()
+                        },
+                    ))
+                    .insert(LobbyText);
+                parent.spawn(TextBundle::from_section(
+                    "Esc to go back to the menu",
+                    TextStyle {
+                        font_size: 20.,
+                        color: Srgba::gray(0.7).into(),
+                        ..default()
+                    },
+                ));
+            });
+    });
+}
+
+fn setup_netplay_ui(mut cmd: Commands) {
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(5.)),
+            ..default()
+        },
+        background_color: Srgba::BLACK.with_alpha(0.8).into(),
+        border_radius: BorderRadius::all(Val::Px(10.)),
+        visibility: Visibility::Hidden,
+        ..default()
+    })
+    .insert(NetplayPanel)
+    .with_children(|parent| {
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font_size: 20.,
+                        color: Srgba::gray(0.7).into(),
+                        ..default()
+                    },
+                ),
+            ]))
+            .insert(NetplayText);
+    });
+}
+
+/// Shows how the session is going, in the lobby and in a corner of the
+/// screen once it's under way.
+fn update_netplay_ui(
+    netplay: Res<Netplay>,
+    state: Res<State<AppState>>,
+    time: Res<Time<Real>>,
+    mut lobby_q: Query<&mut Text, (With<LobbyText>, Without<NetplayText>)>,
+    mut text_q: Query<&mut Text, With<NetplayText>>,
+    mut panel_q: Query<&mut Visibility, With<NetplayPanel>>,
+) {
+    let state = *state.get();
+    let (status, detail) = match &netplay.phase {
+        Phase::Lobby => match netplay.role {
+            Role::Host { port } => (format!("Waiting for a player to join on port {port}"), ""),
+            Role::Join { address } => (format!("Joining {address}"), ""),
+        },
+        &Phase::Connected { peer } => {
+            let game = netplay.game.unwrap_or(0);
+            let stalled = netplay
+                .stalled_since
+                .is_some_and(|since| time.elapsed() - since > STALL_NOTICE);
+            let detail = if state == AppState::Playing && netplay.peer_game < game {
+                "\nWaiting for the other player to restart"
+            } else if state == AppState::GameOver && netplay.peer_game > game {
+                "\nThe other player wants a rematch"
+            } else if state == AppState::Playing && stalled {
+                "\nWaiting for the other player"
+            } else {
+                ""
+            };
+            (format!("Online with {peer}"), detail)
+        }
+        Phase::Ended(reason) => (reason.clone(), ""),
+    };
+
+    // Only touch the text when it changes, so it isn't laid out again every
+    // frame
+    for mut text in &mut lobby_q {
+        if text.sections[0].value != status {
+            text.sections[0].value.clone_from(&status);
+        }
+    }
+    if let Ok(mut text) = text_q.get_single_mut() {
+        if text.sections[0].value != status || text.sections[1].value != detail {
+            text.sections[0].value = status;
+            text.sections[1].value = detail.to_string();
+        }
+    }
+    if let Ok(mut visibility) = panel_q.get_single_mut() {
+        // A finished session is only mentioned until the next game starts
+        let shown = match netplay.phase {
+            Phase::Lobby => false,
+            Phase::Connected { .. } => true,
+            Phase::Ended(_) => state != AppState::Playing && state != AppState::Lobby,
+        };
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Waits for the next message to arrive at `netplay` and handles it.
+    fn receive(netplay: &mut Netplay, config: &mut GameConfig) -> Option<Change> {
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+        let start = Instant::now();
+        loop {
+            match netplay.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    let message = serde_json::from_slice(&buffer[..len]).unwrap();
+                    return netplay.handle(message, from, config, Duration::ZERO);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    assert!(start.elapsed() < Duration::from_secs(5), "nothing arrived");
+                    std::thread::yield_now();
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    /// Waits for the next message to arrive at a plain socket.
+    fn receive_on(socket: &UdpSocket) -> Message {
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+        let (len, _) = socket.recv_from(&mut buffer).unwrap();
+        serde_json::from_slice(&buffer[..len]).unwrap()
+    }
+
+    fn address(netplay: &Netplay) -> SocketAddr {
+        let port = netplay.socket.local_addr().unwrap().port();
+        (Ipv4Addr::LOCALHOST, port).into()
+    }
+
+    fn join_message() -> Message {
+        Message::Join {
+            version: PROTOCOL_VERSION,
+        }
+    }
+
+    /// A host and a client that have shaken hands and started their first
+    /// game, with their settings.
+    fn connect() -> ((Netplay, GameConfig), (Netplay, GameConfig)) {
+        let mut host = Netplay::host(0).unwrap();
+        let mut client = Netplay::join(&address(&host).to_string()).unwrap();
+        let mut host_config = GameConfig {
+            seed: Some(7),
+            ..GameConfig::default()
+        };
+        let mut client_config = GameConfig::default();
+
+        client.send(address(&host), &join_message());
+        assert!(matches!(
+            receive(&mut host, &mut host_config),
+            Some(Change::Started)
+        ));
+        assert!(matches!(
+            receive(&mut client, &mut client_config),
+            Some(Change::Started)
+        ));
+        assert_eq!(host.start_game(), 0);
+        assert_eq!(client.start_game(), 0);
+        ((host, host_config), (client, client_config))
+    }
+
+    #[test]
+    fn shakes_hands_with_the_host_settings() {
+        let ((host, host_config), (client, client_config)) = connect();
+        assert_eq!((host.local, client.local), (0, 1));
+        assert_eq!(host.seed, 7);
+        assert_eq!(client.seed, 7);
+        assert_eq!(host_config.players, 2);
+        assert_eq!(client_config.players, 2);
+        assert_eq!(client.input_delay, host.input_delay);
+    }
+
+    #[test]
+    fn exchanges_moves_and_acks_them() {
+        let ((mut host, mut host_config), (mut client, mut client_config)) = connect();
+        let delay = host.input_delay;
+        assert!(
+            host.ready(),
+            "nobody's turns are due during the input delay"
+        );
+
+        let sent = [Direction::Up, Direction::Left, Direction::Down];
+        host.moves_mut().unwrap().local.extend(sent);
+        host.send_moves();
+        assert!(receive(&mut client, &mut client_config).is_none());
+        assert_eq!(client.moves().unwrap().remote, sent);
+
+        // Moves sent again before the ack arrives aren't stored twice
+        host.send_moves();
+        assert!(receive(&mut client, &mut client_config).is_none());
+        assert_eq!(client.moves().unwrap().remote, sent);
+
+        client.send_moves();
+        assert!(receive(&mut host, &mut host_config).is_none());
+        assert_eq!(host.moves().unwrap().acked, sent.len());
+
+        // Only the moves after the acked ones go out next time
+        host.moves_mut().unwrap().local.push(Direction::Right);
+        host.send_moves();
+        assert!(receive(&mut client, &mut client_config).is_none());
+        assert_eq!(client.moves().unwrap().remote.len(), sent.len() + 1);
+
+        // The host waits on the client's turn for the first move after the
+        // input delay, the client has the host's
+        host.tick = delay;
+        client.tick = delay;
+        assert!(!host.ready());
+        assert!(client.ready());
+    }
+
+    #[test]
+    fn compares_checksums_both_sides_have() {
+        let mut moves = GameMoves::default();
+        moves.checksums.insert(20, 1);
+        moves.remote_checksums.insert(20, 1);
+        moves.remote_checksums.insert(40, 2);
+        assert_eq!(moves.compare_checksums(), None);
+        // Only the one that couldn't be compared yet is kept
+        assert_eq!(moves.remote_checksums.len(), 1);
+
+        moves.checksums.insert(40, 3);
+        assert_eq!(moves.compare_checksums(), Some(40));
+        assert!(moves.remote_checksums.is_empty());
+    }
+
+    #[test]
+    fn ends_the_session_on_a_desync() {
+        let ((mut host, mut host_config), (mut client, mut client_config)) = connect();
+        host.moves_mut().unwrap().checksums.insert(20, 1);
+        client.moves_mut().unwrap().checksums.insert(20, 2);
+
+        client.send_moves();
+        assert!(matches!(
+            receive(&mut host, &mut host_config),
+            Some(Change::Ended)
+        ));
+        assert!(matches!(&host.phase, Phase::Ended(reason) if reason.contains("move 20")));
+        assert!(matches!(
+            receive(&mut client, &mut client_config),
+            Some(Change::Ended)
+        ));
+        assert!(matches!(client.phase, Phase::Ended(_)));
+    }
+
+    #[test]
+    fn keeps_the_session_settings_until_restored() {
+        let ((mut host, mut host_config), (mut client, mut client_config)) = connect();
+        client.leave(String::new(), String::new());
+        assert!(matches!(
+            receive(&mut host, &mut host_config),
+            Some(Change::Ended)
+        ));
+
+        // The last game is still on the screen
+        assert_eq!(host_config.players, 2);
+        assert_eq!(client_config.players, 2);
+
+        host.restore_config(&mut host_config);
+        client.restore_config(&mut client_config);
+        assert_eq!(host_config.players, 1);
+        assert_eq!(client_config.players, 1);
+    }
+
+    #[test]
+    fn refuses_other_players_and_versions() {
+        let ((mut host, mut host_config), (client, _)) = connect();
+        let peer = host.peer();
+        let stranger = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let join = serde_json::to_vec(&join_message()).unwrap();
+        stranger.send_to(&join, address(&host)).unwrap();
+        assert!(receive(&mut host, &mut host_config).is_none());
+        assert!(matches!(receive_on(&stranger), Message::Refuse { .. }));
+        assert_eq!(host.peer(), peer);
+        assert_eq!(
+            peer.map(|peer| peer.port()),
+            client
+                .socket
+                .local_addr()
+                .ok()
+                .map(|address| address.port())
+        );
+
+        let mut lobby = Netplay::host(0).unwrap();
+        let mut lobby_config = GameConfig::default();
+        let old = serde_json::to_vec(&Message::Join { version: 0 }).unwrap();
+        stranger.send_to(&old, address(&lobby)).unwrap();
+        assert!(receive(&mut lobby, &mut lobby_config).is_none());
+        assert!(matches!(receive_on(&stranger), Message::Refuse { .. }));
+        assert!(matches!(lobby.phase, Phase::Lobby));
+        assert_eq!(lobby_config.players, 1);
+    }
+}
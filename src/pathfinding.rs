@@ -0,0 +1,331 @@
+//! The original autopilot: head for the food along the shortest path, or
+//! for the most open space when the food can't be reached.
+//!
+//! Before going for the food it plays the path out on a copy of the game and
+//! checks the snake could still reach its tail afterwards. Chasing the tail
+//! always leaves a way out, so if eating would seal the snake into a pocket
+//! it follows its tail instead until the food is safe to take.
+//!
+//! Other snakes are obstacles that clear from the tail end like the snake's
+//! own body, and it steers clear of the cells their heads could move onto
+//! next unless there is nowhere else to go.
+
+use crate::{
+    controller::{Plan, SnakeController},
+    game::{Direction, GameState, Occupancy, StepOutcome},
+};
+use bevy::math::IVec2;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+/// Follows an A* path to the food when that's safe, or else its tail,
+/// falling back to whichever move leads into the largest open area.
+#[derive(Default)]
+pub struct Pathfinding {
+    /// Moves along the path to the food that are played out before checking
+    /// it's safe, or `None` to play out the whole path.
+    lookahead: Option<usize>,
+    plan: Plan,
+}
+
+#[derive(Copy, Clone)]
+struct Node {
+    position: IVec2,
+    previous: Option<IVec2>,
+    f_score: i32,
+    g_score: i32,
+}
+
+impl Eq for Node {}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other
+            .f_score
+            .cmp(&self.f_score)
+            .then(other.position.x.cmp(&self.position.x))
+            .then(other.position.y.cmp(&self.position.y))
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.position == other.position
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Pathfinding {
+    /// Only checks the first `lookahead` moves to the food are safe, which
+    /// makes it quicker to go for the food and easier to trap.
+    pub fn with_lookahead(lookahead: usize) -> Self {
+        Pathfinding {
+            lookahead: Some(lookahead),
+            plan: Plan::default(),
+        }
+    }
+}
+
+impl SnakeController for Pathfinding {
+    fn next_direction(&mut self, game: &GameState) -> Option<Direction> {
+        let occupancy = game.occupancy();
+        let start = game.head();
+        let contested = contested_cells(game);
+        self.plan = Plan::default();
+
+        if let Some(food) = game.food() {
+            let (path, explored) = search(game, food);
+            self.plan.explored = explored.into_keys().collect();
+            if path.len() > 1
+                && !contested.contains(&path[1])
+                && tail_reachable_after(game, &path, self.lookahead)
+            {
+                self.plan.goal = "Chase food";
+                self.plan.path = path;
+                return occupancy.direction_between(start, self.plan.path[1]);
+            }
+        }
+
+        if let Some((direction, path)) = follow_tail(game, &contested) {
+            self.plan.goal = "Follow tail";
+            self.plan.path = path;
+            return Some(direction);
+        }
+
+        self.plan.goal = "Survival";
+        survival_mode(start, occupancy, &contested, &mut self.plan.areas)
+    }
+
+    fn plan(&self) -> Option<&Plan> {
+        Some(&self.plan)
+    }
+}
+
+/// Picks a move after which the snake can still reach its tail, taking the
+/// one with the longest way round to the tail.
+///
+/// Always taking the shortest way would have the snake circle the same loop
+/// forever without its shape ever changing, so the food might never become
+/// safe to go for.
+///
+/// Moves onto a cell in `contested` are left out.
+///
+/// Returns the move along with the path it leads to the tail, head first.
+fn follow_tail(game: &GameState, contested: &HashSet<IVec2>) -> Option<(Direction, Vec<IVec2>)> {
+    let mut best = None;
+    let mut longest = 1;
+
+    for direction in Direction::ALL {
+        // Turning back is ignored, so it would only repeat going straight
+        // under the wrong name
+        if direction == game.direction().opposite() {
+            continue;
+        }
+        let next = game.occupancy().neighbor(game.head(), direction);
+        if next.is_some_and(|next| contested.contains(&next)) {
+            continue;
+        }
+
+        let mut future = game.clone();
+        let (length, path) = match future.step(direction) {
+            StepOutcome::Moved | StepOutcome::Ate => {
+                let path = find_path(&future, *future.body().back().unwrap());
+                (path.len(), path)
+            }
+            StepOutcome::Won => (usize::MAX, vec![future.head()]),
+            StepOutcome::Died(_) => continue,
+        };
+        if length > longest {
+            longest = length;
+            let path = std::iter::once(game.head()).chain(path).collect();
+            best = Some((direction, path));
+        }
+    }
+    best
+}
+
+/// Whether the snake could still find its way to its tail after following
+/// `path` to the food, or its first `lookahead` moves if given.
+fn tail_reachable_after(game: &GameState, path: &[IVec2], lookahead: Option<usize>) -> bool {
+    let mut future = game.clone();
+    for step in path.windows(2).take(lookahead.unwrap_or(usize::MAX)) {
+        let direction = future.occupancy().direction_between(step[0], step[1]);
+        match future.step(direction.unwrap()) {
+            StepOutcome::Moved | StepOutcome::Ate => {}
+            StepOutcome::Won => return true,
+            StepOutcome::Died(_) => return false,
+        }
+    }
+
+    let tail = *future.body().back().unwrap();
+    find_path(&future, tail).len() > 1
+}
+
+/// Picks the move leading into the largest open area, adding the area found
+/// for each move to `areas`. Moves onto a cell in `contested` are only taken
+/// if there's no other way to go.
+fn survival_mode(
+    start: IVec2,
+    occupancy: &Occupancy,
+    contested: &HashSet<IVec2>,
+    areas: &mut Vec<(Direction, Vec<IVec2>)>,
+) -> Option<Direction> {
+    // Ranked by whether the move is uncontested, then by the area behind it
+    let mut best = None;
+
+    for direction in Direction::ALL {
+        let Some(next_pos) = occupancy.neighbor(start, direction) else {
+            continue;
+        };
+
+        if occupancy.is_free(next_pos) {
+            let area = flood_fill(next_pos, occupancy);
+            let rank = (!contested.contains(&next_pos), area.len());
+            if best.is_none_or(|(best_rank, _)| rank > best_rank) {
+                best = Some((rank, direction));
+            }
+            areas.push((direction, area.into_iter().collect()));
+        }
+    }
+    best.map(|(_, direction)| direction)
+}
+
+/// Cells the other snakes' heads could move onto next. Moving onto one as
+/// well risks a head-on collision.
+fn contested_cells(game: &GameState) -> HashSet<IVec2> {
+    let occupancy = game.occupancy();
+    game.snakes()[1..]
+        .iter()
+        .filter(|snake| snake.is_alive())
+        .flat_map(|snake| {
+            Direction::ALL
+                .into_iter()
+                .filter(|&direction| direction != snake.direction().opposite())
+                .filter_map(|direction| occupancy.neighbor(snake.head(), direction))
+        })
+        .collect()
+}
+
+/// Finds the shortest path from the head to `end`, returned head first, or
+/// an empty path if there is none.
+///
+/// The body moves along with the head, so it isn't treated as a wall: every
+/// move the tail gets shorter by a cell, and the path may run over a body
+/// cell once it has been vacated. A cell only counts as vacated after the
+/// move that pops it off the tail, as moving onto the tail itself is fatal.
+fn find_path(game: &GameState, end: IVec2) -> Vec<IVec2> {
+    search(game, end).0
+}
+
+/// `find_path`, also returning every cell the search reached.
+fn search(game: &GameState, end: IVec2) -> (Vec<IVec2>, HashMap<IVec2, Node>) {
+    let occupancy = game.occupancy();
+    let start = game.head();
+    let vacated = vacated_after(game);
+
+    let mut cells = HashMap::new();
+    let mut open_list = BinaryHeap::new();
+
+    let node = Node {
+        position: start,
+        previous: None,
+        f_score: 0,
+        g_score: 0,
+    };
+    cells.insert(start, node);
+    open_list.push(node);
+
+    while let Some(Node {
+        position: mut current,
+        ..
+    }) = open_list.pop()
+    {
+        // Reached the end, reconstruct path
+        if current == end {
+            let mut path = vec![current];
+            while let Some(&Node {
+                previous: Some(previous),
+                ..
+            }) = cells.get(&current)
+            {
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return (path, cells);
+        }
+
+        let neighbors = Direction::ALL
+            .iter()
+            .filter_map(|&direction| occupancy.neighbor(current, direction));
+
+        for neighbor in neighbors {
+            // The number of moves it takes to get to the neighbour
+            let g_score = cells[&current].g_score + 1;
+            let free = occupancy.is_free(neighbor)
+                || vacated.get(&neighbor).is_some_and(|&moves| moves < g_score);
+            if !free {
+                continue;
+            }
+
+            let h_score = occupancy.distance(neighbor, end);
+            let f_score = g_score + h_score;
+
+            if !cells.contains_key(&neighbor) || g_score < cells[&neighbor].g_score {
+                let node = Node {
+                    position: neighbor,
+                    previous: Some(current),
+                    f_score,
+                    g_score,
+                };
+                cells.insert(neighbor, node);
+                open_list.push(node);
+            }
+        }
+    }
+    (vec![], cells)
+}
+
+/// How many moves it takes until each cell covered by a living snake is no
+/// longer covered, as long as no snake eats on the way. Dead snakes never
+/// move off their cells.
+fn vacated_after(game: &GameState) -> HashMap<IVec2, i32> {
+    game.snakes()
+        .iter()
+        .filter(|snake| snake.is_alive())
+        .flat_map(|snake| {
+            let len = snake.body().len() as i32;
+            snake
+                .body()
+                .iter()
+                .enumerate()
+                .map(move |(i, &pos)| (pos, len - i as i32))
+        })
+        .collect()
+}
+
+/// Every free cell that can be reached from `start`.
+fn flood_fill(start: IVec2, occupancy: &Occupancy) -> HashSet<IVec2> {
+    let mut stack = vec![start];
+    let mut visited = HashSet::new();
+
+    while let Some(pos) = stack.pop() {
+        if !visited.contains(&pos) && occupancy.is_free(pos) {
+            visited.insert(pos);
+            stack.extend(
+                Direction::ALL
+                    .iter()
+                    .filter_map(|&direction| occupancy.neighbor(pos, direction)),
+            );
+        }
+    }
+
+    visited
+}
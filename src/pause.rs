@@ -1,7 +1,11 @@
+use crate::{actions::Action, state::AppState};
 use bevy::prelude::*;
 
 pub struct PausePlugin;
 
+#[derive(Component)]
+struct PauseButton;
+
 #[derive(Component)]
 struct PauseButtonText;
 
@@ -13,7 +17,10 @@ impl Plugin for PausePlugin {
         app.add_systems(Startup, setup_pause_button)
             .add_systems(
                 Update,
-                (handle_pause_button_click, toggle_pause, update_pause_button),
+                (
+                    (handle_pause_button_click, toggle_pause).run_if(in_state(AppState::Playing)),
+                    update_pause_button,
+                ),
             )
             .insert_resource(IsPaused(false));
     }
@@ -34,6 +41,7 @@ fn setup_pause_button(mut cmd: Commands) {
         border_radius: BorderRadius::all(Val::Px(10.)),
         ..default()
     })
+    .insert(PauseButton)
     .with_children(|parent| {
         parent
             .spawn(TextBundle::from_section(
@@ -50,7 +58,7 @@ fn setup_pause_button(mut cmd: Commands) {
 
 fn handle_pause_button_click(
     mut is_paused: ResMut<IsPaused>,
-    mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>)>,
+    mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<PauseButton>)>,
 ) {
     for &interaction in &mut interaction_query {
         if interaction == Interaction::Pressed {
@@ -59,8 +67,8 @@ fn handle_pause_button_click(
     }
 }
 
-fn toggle_pause(mut is_paused: ResMut<IsPaused>, input: Res<ButtonInput<KeyCode>>) {
-    if input.just_pressed(KeyCode::Space) {
+fn toggle_pause(mut is_paused: ResMut<IsPaused>, actions: Res<ButtonInput<Action>>) {
+    if actions.just_pressed(Action::Pause) {
         is_paused.0 = !is_paused.0;
     }
 }
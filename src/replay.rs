@@ -0,0 +1,429 @@
+use crate::{
+    actions::Action,
+    autopilot::AutopilotSet,
+    config::GameConfig,
+    game::Direction,
+    input::NextDirection,
+    pause::IsPaused,
+    snake::{Game, SetupSnakeSet, SnakeId, SnakeMoveEvent, SnakeMoveSet, SnakeMoveTimerTickSet},
+    state::AppState,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Playback speeds that can be stepped through, as multiples of real time.
+const PLAYBACK_SPEEDS: [f32; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+
+/// Most moves a snake can have in a replay file, far more than it takes to
+/// fill the largest board, so a corrupt run length can't use up all memory.
+const MAX_REPLAY_MOVES: usize = 1 << 24;
+
+/// Records games to a replay file and plays them back.
+pub struct ReplayPlugin {
+    /// File the first finished game is written to. Later games go to
+    /// numbered files next to it.
+    pub record: Option<PathBuf>,
+    /// Replay to play back instead of letting anyone steer.
+    pub replay: Option<Replay>,
+}
+
+/// Everything needed to play a game again move for move: the RNG seed, the
+/// settings it was started with and the direction fed into every move.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    /// Moves of the first snake.
+    #[serde(with = "run_length")]
+    pub moves: Vec<Direction>,
+    /// Moves of every other snake, second snake first. Each has as many
+    /// moves as the first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_moves: Vec<Moves>,
+}
+
+/// Every move of one snake.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Moves(#[serde(with = "run_length")] pub Vec<Direction>);
+
+/// Moves of the game in progress, written to a file named after `path` when
+/// it ends.
+#[derive(Resource)]
+struct Recording {
+    path: PathBuf,
+    replay: Option<Replay>,
+    /// Games saved so far.
+    saved: u32,
+}
+
+/// Present while a replay is being played back instead of a live game.
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    next_move: usize,
+    speed_index: usize,
+}
+
+#[derive(Component)]
+struct PlaybackText;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(path) = &self.record {
+            app.insert_resource(Recording {
+                path: path.clone(),
+                replay: None,
+                saved: 0,
+            })
+            .add_systems(
+                OnEnter(AppState::Playing),
+                start_recording.after(SetupSnakeSet),
+            )
+            .add_systems(OnEnter(AppState::GameOver), save_recording)
+            .add_systems(Update, record_move.in_set(SnakeMoveSet));
+        }
+
+        if let Some(replay) = &self.replay {
+            app.insert_resource(Playback {
+                replay: replay.clone(),
+                next_move: 0,
+                speed_index: PLAYBACK_SPEEDS.iter().position(|&s| s == 1.).unwrap(),
+            })
+            .add_systems(Startup, setup_playback_ui)
+            .add_systems(
+                OnEnter(AppState::Playing),
+                rewind_playback.after(SetupSnakeSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    step_playback.in_set(SnakeMoveTimerTickSet),
+                    feed_replay_move.in_set(AutopilotSet),
+                    (change_playback_speed, update_playback_ui)
+                        .chain()
+                        .run_if(in_state(AppState::Playing)),
+                ),
+            );
+        }
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, default()).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    /// Settings to start the replayed game with.
+    pub fn game_config(&self) -> GameConfig {
+        GameConfig {
+            seed: Some(self.seed),
+            autopilot: false,
+            // Nothing steers during playback, so a bot named in the file
+            // would only be run for nothing
+            bot: None,
+            ..self.config.clone()
+        }
+    }
+}
+
+/// Stores moves as a string of direction letters with run lengths, such as
+/// `R12U3L`, which keeps long games down to a few bytes per turn.
+mod run_length {
+    use super::MAX_REPLAY_MOVES;
+    use crate::game::Direction;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    fn letter(direction: Direction) -> char {
+        match direction {
+            Direction::Up => 'U',
+            Direction::Down => 'D',
+            Direction::Left => 'L',
+            Direction::Right => 'R',
+        }
+    }
+
+    fn direction(letter: char) -> Option<Direction> {
+        match letter {
+            'U' => Some(Direction::Up),
+            'D' => Some(Direction::Down),
+            'L' => Some(Direction::Left),
+            'R' => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn serialize<S: Serializer>(moves: &[Direction], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut encoded = String::new();
+        for run in moves.chunk_by(|a, b| a == b) {
+            encoded.push(letter(run[0]));
+            if run.len() > 1 {
+                encoded.push_str(&run.len().to_string());
+            }
+        }
+        serializer.serialize_str(&encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Direction>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let mut moves = Vec::new();
+        let mut chars = encoded.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let direction =
+                direction(c).ok_or_else(|| D::Error::custom(format!("invalid move `{c}`")))?;
+            let mut count = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                count.push(digit);
+            }
+            let count = if count.is_empty() {
+                1
+            } else {
+                count.parse().map_err(D::Error::custom)?
+            };
+            if count > MAX_REPLAY_MOVES - moves.len() {
+                return Err(D::Error::custom(format!(
+                    "more than {MAX_REPLAY_MOVES} moves"
+                )));
+            }
+            moves.extend(std::iter::repeat_n(direction, count));
+        }
+        Ok(moves)
+    }
+}
+
+fn start_recording(mut recording: ResMut<Recording>, config: Res<GameConfig>, game: Res<Game>) {
+    recording.replay = Some(Replay {
+        seed: game.0.seed(),
+        config: config.clone(),
+        moves: Vec::new(),
+        other_moves: vec![Moves::default(); game.0.snakes().len() - 1],
+    });
+}
+
+fn record_move(
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut recording: ResMut<Recording>,
+    snake_q: Query<(&SnakeId, &NextDirection)>,
+) {
+    if ev_move.is_empty() {
+        return;
+    }
+    ev_move.clear();
+
+    let Some(replay) = &mut recording.replay else {
+        return;
+    };
+    for (&SnakeId(id), next_direction) in &snake_q {
+        match id {
+            0 => replay.moves.push(next_direction.0),
+            _ => replay.other_moves[id - 1].0.push(next_direction.0),
+        }
+    }
+}
+
+/// Where game number `game`, counting from 1, is recorded when `--record`
+/// names `path`: `path` itself for the first game, then `game-2.ron`,
+/// `game-3.ron` and so on for `game.ron`.
+fn numbered_path(path: &Path, game: u32) -> PathBuf {
+    if game == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{game}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{game}"),
+    };
+    path.with_file_name(name)
+}
+
+fn save_recording(mut recording: ResMut<Recording>) {
+    let Some(replay) = &recording.replay else {
+        return;
+    };
+
+    let path = numbered_path(&recording.path, recording.saved + 1);
+    match replay.save(&path) {
+        Ok(()) => {
+            info!("Saved replay to {}", path.display());
+            recording.saved += 1;
+        }
+        Err(e) => warn!("Couldn't save replay to {}: {e}", path.display()),
+    }
+}
+
+fn rewind_playback(mut is_paused: ResMut<IsPaused>, mut playback: ResMut<Playback>) {
+    playback.next_move = 0;
+    is_paused.0 = playback.replay.moves.is_empty();
+}
+
+fn feed_replay_move(
+    mut ev_move: EventReader<SnakeMoveEvent>,
+    mut is_paused: ResMut<IsPaused>,
+    mut snake_q: Query<(&SnakeId, &mut NextDirection)>,
+    mut playback: ResMut<Playback>,
+) {
+    if ev_move.is_empty() {
+        return;
+    }
+    ev_move.clear();
+
+    if playback.next_move < playback.replay.moves.len() {
+        for (&SnakeId(id), mut next_direction) in &mut snake_q {
+            let moves = match id {
+                0 => Some(&playback.replay.moves),
+                _ => playback.replay.other_moves.get(id - 1).map(|m| &m.0),
+            };
+            if let Some(&direction) = moves.and_then(|m| m.get(playback.next_move)) {
+                next_direction.0 = direction;
+            }
+        }
+        playback.next_move += 1;
+    }
+    // Stop before running out of moves in case the recording ended while the
+    // snake was still alive
+    if playback.next_move == playback.replay.moves.len() {
+        is_paused.0 = true;
+    }
+}
+
+/// Moves the snake by exactly one step while playback is paused.
+fn step_playback(
+    mut ev_move: EventWriter<SnakeMoveEvent>,
+    actions: Res<ButtonInput<Action>>,
+    is_paused: Res<IsPaused>,
+    playback: Res<Playback>,
+) {
+    let moves_left = playback.next_move < playback.replay.moves.len();
+    if is_paused.0 && moves_left && actions.just_pressed(Action::StepPlayback) {
+        ev_move.send(SnakeMoveEvent);
+    }
+}
+
+fn change_playback_speed(
+    mut playback: ResMut<Playback>,
+    mut time: ResMut<Time<Virtual>>,
+    actions: Res<ButtonInput<Action>>,
+) {
+    if actions.just_pressed(Action::SlowerPlayback) {
+        playback.speed_index = playback.speed_index.saturating_sub(1);
+    } else if actions.just_pressed(Action::FasterPlayback) {
+        playback.speed_index = (playback.speed_index + 1).min(PLAYBACK_SPEEDS.len() - 1);
+    } else {
+        return;
+    }
+
+    time.set_relative_speed(PLAYBACK_SPEEDS[playback.speed_index]);
+}
+
+fn setup_playback_ui(mut cmd: Commands, config: Res<GameConfig>) {
+    let text_style = TextStyle {
+        font_size: 30.,
+        ..default()
+    };
+    let hint_style = TextStyle {
+        font_size: 20.,
+        color: Srgba::gray(0.7).into(),
+        ..default()
+    };
+
+    let key = |action| config.key(action);
+    let mut hints = Vec::new();
+    if let (Some(slower), Some(faster)) = (key(Action::SlowerPlayback), key(Action::FasterPlayback))
+    {
+        hints.push(format!("{slower} {faster} speed"));
+    }
+    if let Some(pause) = key(Action::Pause) {
+        hints.push(format!("{pause} pause"));
+    }
+    if let Some(step) = key(Action::StepPlayback) {
+        hints.push(format!("{step} step"));
+    }
+    let hint = format!("\n{}", hints.join(", "));
+
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(5.)),
+            ..default()
+        },
+        background_color: Srgba::BLACK.with_alpha(0.8).into(),
+        border_radius: BorderRadius::all(Val::Px(10.)),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new("Replay ", text_style.clone()),
+                TextSection::new("", text_style),
+                TextSection::new(hint, hint_style),
+            ]))
+            .insert(PlaybackText);
+    });
+}
+
+fn update_playback_ui(playback: Res<Playback>, mut query: Query<&mut Text, With<PlaybackText>>) {
+    if !playback.is_changed() {
+        return;
+    }
+
+    let mut text = query.single_mut();
+    text.sections[1].value = format!(
+        "{}x  {}/{}",
+        PLAYBACK_SPEEDS[playback.speed_index],
+        playback.next_move,
+        playback.replay.moves.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(encoded: &str) -> Result<Vec<Direction>, String> {
+        ron::from_str::<Moves>(&format!("\"{encoded}\""))
+            .map(|moves| moves.0)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn reads_run_lengths() {
+        use Direction::*;
+        assert_eq!(
+            moves("R3UL2"),
+            Ok(vec![Right, Right, Right, Up, Left, Left])
+        );
+        assert_eq!(moves(""), Ok(Vec::new()));
+        assert!(moves("R3X").is_err());
+        assert!(moves("R99999999999999999999999").is_err());
+    }
+
+    #[test]
+    fn caps_the_number_of_moves() {
+        assert!(moves(&format!("R{MAX_REPLAY_MOVES}")).is_ok());
+        assert!(moves(&format!("R{MAX_REPLAY_MOVES}U")).is_err());
+        assert!(moves(&format!("U{}", usize::MAX)).is_err());
+    }
+
+    #[test]
+    fn numbers_every_game_after_the_first() {
+        let path = Path::new("replays/game.ron");
+        assert_eq!(numbered_path(path, 1), Path::new("replays/game.ron"));
+        assert_eq!(numbered_path(path, 2), Path::new("replays/game-2.ron"));
+        assert_eq!(numbered_path(Path::new("game"), 3), Path::new("game-3"));
+    }
+}
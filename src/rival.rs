@@ -0,0 +1,83 @@
+//! Computer-controlled snakes that compete with the players for the food.
+//!
+//! A rival steers with the autopilot's pathfinding bot, held back according
+//! to its difficulty: easier rivals sometimes react a move late and look
+//! less far ahead before going for the food.
+
+use crate::{
+    controller::{Plan, SnakeController},
+    game::{Direction, GameState},
+    pathfinding::Pathfinding,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// Chance on each move that the rival reacts late and keeps going
+    /// straight instead of turning.
+    fn slip_chance(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.05,
+            Difficulty::Hard => 0.,
+        }
+    }
+
+    /// Moves along the path to the food the rival checks are safe before
+    /// taking it, or `None` to check the whole path.
+    fn lookahead(self) -> Option<usize> {
+        match self {
+            Difficulty::Easy => Some(0),
+            Difficulty::Normal => Some(8),
+            Difficulty::Hard => None,
+        }
+    }
+}
+
+/// Steers a rival snake. It always steers the first snake of the game it's
+/// given, so the game has to be seen from the rival's side with
+/// `GameState::seen_by`.
+pub struct RivalController {
+    pathfinding: Pathfinding,
+    slip_chance: f64,
+    rng: ChaCha8Rng,
+}
+
+impl RivalController {
+    /// A rival whose mistakes are drawn from an RNG seeded with `seed`.
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        RivalController {
+            pathfinding: match difficulty.lookahead() {
+                Some(lookahead) => Pathfinding::with_lookahead(lookahead),
+                None => Pathfinding::default(),
+            },
+            slip_chance: difficulty.slip_chance(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl SnakeController for RivalController {
+    fn next_direction(&mut self, game: &GameState) -> Option<Direction> {
+        let direction = self.pathfinding.next_direction(game);
+        if self.rng.gen_bool(self.slip_chance) {
+            return None;
+        }
+        direction
+    }
+
+    fn plan(&self) -> Option<&Plan> {
+        self.pathfinding.plan()
+    }
+}
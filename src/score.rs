@@ -1,53 +1,132 @@
-use crate::food::EatEvent;
+use crate::{
+    config::GameConfig,
+    game::GameState,
+    snake::{snake_color, Game},
+};
 use bevy::prelude::*;
 
-#[derive(Component, Default)]
-struct Score(u32);
+/// Row holding a score panel for each snake.
+#[derive(Component)]
+struct ScorePanels;
+
+/// Score of the snake with this index.
+#[derive(Component)]
+struct ScoreText(usize);
 
 pub struct ScorePlugin;
 
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_score_ui)
-            .add_systems(Update, update_score_ui);
+            .add_systems(Update, update_score_ui.run_if(resource_exists::<Game>));
     }
 }
 
 fn setup_score_ui(mut cmd: Commands) {
-    let text_style = TextStyle {
-        font_size: 40.,
-        ..default()
-    };
-
     cmd.spawn(NodeBundle {
         style: Style {
             position_type: PositionType::Absolute,
             left: Val::Px(10.),
             top: Val::Px(10.),
-            padding: UiRect::all(Val::Px(5.)),
+            column_gap: Val::Px(10.),
             ..default()
         },
-        background_color: Srgba::BLACK.with_alpha(0.8).into(),
-        border_radius: BorderRadius::all(Val::Px(10.)),
         ..default()
     })
-    .with_children(|parent| {
-        parent
-            .spawn(TextBundle::from_sections([
-                TextSection::new("Score: ", text_style.clone()),
-                TextSection::new("0", text_style),
-            ]))
-            .insert(Score(0));
-    });
+    .insert(ScorePanels);
+}
+
+/// Adds the panel for snake `i`. The first one also shows the game's seed.
+fn spawn_score_panel(parent: &mut ChildBuilder, game: &GameState, config: &GameConfig, i: usize) {
+    let snakes = game.snakes().len();
+    let text_style = TextStyle {
+        font_size: 40.,
+        color: score_color(game, config, i),
+        ..default()
+    };
+    let seed_style = TextStyle {
+        font_size: 20.,
+        color: Srgba::gray(0.7).into(),
+        ..default()
+    };
+
+    let label = if snakes == 1 {
+        "Score: ".to_string()
+    } else if i < config.players {
+        format!("P{}: ", i + 1)
+    } else {
+        format!("R{}: ", i - config.players + 1)
+    };
+    let mut sections = vec![
+        TextSection::new(label, text_style.clone()),
+        TextSection::new(game.snakes()[i].score().to_string(), text_style),
+    ];
+    if i == 0 {
+        sections.push(TextSection::new("\nSeed: ", seed_style.clone()));
+        sections.push(TextSection::new(game.seed().to_string(), seed_style));
+    }
+
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                padding: UiRect::all(Val::Px(5.)),
+                align_self: AlignSelf::FlexStart,
+                ..default()
+            },
+            background_color: Srgba::BLACK.with_alpha(0.8).into(),
+            border_radius: BorderRadius::all(Val::Px(10.)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_sections(sections))
+                .insert(ScoreText(i));
+        });
 }
 
-fn update_score_ui(mut ev_eat: EventReader<EatEvent>, mut q: Query<(&mut Text, &mut Score)>) {
-    if ev_eat.is_empty() {
+/// Each score is shown in its snake's colour, greyed out once the snake is
+/// dead.
+fn score_color(game: &GameState, config: &GameConfig, i: usize) -> Color {
+    if game.snakes().len() == 1 {
+        Color::WHITE
+    } else if game.snakes()[i].is_alive() {
+        snake_color(config, i).into()
+    } else {
+        Srgba::gray(0.5).into()
+    }
+}
+
+fn update_score_ui(
+    mut cmd: Commands,
+    game: Res<Game>,
+    config: Res<GameConfig>,
+    panels_q: Query<(Entity, Option<&Children>), With<ScorePanels>>,
+    mut text_q: Query<(&mut Text, &ScoreText)>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+
+    // A new game can have a different number of snakes than the last one
+    let (panels, children) = panels_q.single();
+    if children.map_or(0, |c| c.len()) != game.0.snakes().len() {
+        cmd.entity(panels)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for i in 0..game.0.snakes().len() {
+                    spawn_score_panel(parent, &game.0, &config, i);
+                }
+            });
         return;
     }
-    ev_eat.clear();
 
-    let (mut text, mut score) = q.single_mut();
-    score.0 += 1;
-    text.sections[1].value = score.0.to_string();
+    for (mut text, &ScoreText(i)) in &mut text_q {
+        let color = score_color(&game.0, &config, i);
+        text.sections[0].style.color = color;
+        text.sections[1].style.color = color;
+        text.sections[1].value = game.0.snakes()[i].score().to_string();
+        if i == 0 {
+            text.sections[3].value = game.0.seed().to_string();
+        }
+    }
 }
@@ -1,7 +1,13 @@
 use crate::{
+    autopilot::Rival,
+    config::GameConfig,
     constants::*,
-    input::{CurrentDirection, NextDirection},
+    food::EatEvent,
+    game::{Direction, GameState, Occupancy, StepOutcome},
+    input::{InputQueue, NextDirection, Player},
     pause::IsPaused,
+    rival::RivalController,
+    state::AppState,
 };
 use bevy::{
     prelude::*,
@@ -11,18 +17,41 @@ use bevy::{
     },
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
 };
-use std::{
-    collections::VecDeque,
-    f32::consts::{FRAC_PI_2, PI},
-};
+use std::f32::consts::{FRAC_PI_2, PI};
 
 pub struct SnakePlugin;
 
 #[derive(Resource)]
-pub struct IsDead(pub bool);
+pub struct Game(pub GameState);
 
-#[derive(Resource)]
-pub struct SnakeBody(pub VecDeque<Entity>);
+/// Board cell an entity is drawn in. Its `Transform` translation follows it.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GridPos(pub IVec2);
+
+/// A snake taking part in the game, by its index in `Game`'s snakes. Its
+/// body, heading and whether it's still alive are all read from there, while
+/// the entity carries what steers it.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SnakeId(pub usize);
+
+/// Entities drawing a snake, in the same order as its body cells in `Game`.
+#[derive(Component, Default)]
+pub struct SnakeSegments(pub Vec<Entity>);
+
+/// Colour of each player's snake, first player first.
+const PLAYER_COLORS: [Srgba; MAX_PLAYERS] = [
+    Srgba::WHITE,
+    Srgba::rgb(0.3, 0.7, 1.),
+    Srgba::rgb(1., 0.8, 0.2),
+    Srgba::rgb(1., 0.4, 0.8),
+];
+
+/// Colour of a dead snake's body, which stays on the board.
+const DEAD_SNAKE_COLOR: Srgba = Srgba::rgb(0.4, 0.4, 0.4);
+
+/// Material a snake is drawn with while it's alive.
+#[derive(Component)]
+struct SnakeMaterial(Handle<ColorMaterial>);
 
 #[derive(Resource)]
 pub struct SnakeMoveTimer(pub Timer);
@@ -40,28 +69,39 @@ pub struct SnakeMoveSet;
 pub struct SnakeMoveTimerTickSet;
 
 #[derive(Resource)]
-struct CornerMesh(Handle<Mesh>);
-
-#[derive(Resource)]
-struct EndMesh(Handle<Mesh>);
+struct SnakeMeshes {
+    corner: Handle<Mesh>,
+    end: Handle<Mesh>,
+    straight: Handle<Mesh>,
+}
 
 #[derive(Resource)]
-pub struct StraightMesh(pub Handle<Mesh>);
+struct DeadSnakeMaterial(Handle<ColorMaterial>);
 
-#[derive(Resource)]
-pub struct SnakeMaterial(pub Handle<ColorMaterial>);
+enum SegmentShape {
+    Corner,
+    End,
+    Straight,
+}
 
 impl Plugin for SnakePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_snake.in_set(SetupSnakeSet))
+        app.add_systems(Startup, setup_snake_assets)
+            .add_systems(
+                OnEnter(AppState::Playing),
+                reset_snake.in_set(SetupSnakeSet),
+            )
             .add_systems(
                 Update,
                 (
                     tick_move_timer.in_set(SnakeMoveTimerTickSet),
-                    move_snake.in_set(SnakeMoveSet),
+                    (move_snake, render_snake).chain().in_set(SnakeMoveSet),
                 ),
             )
-            .insert_resource(IsDead(false))
+            .add_systems(
+                PostUpdate,
+                sync_grid_transforms.before(TransformSystem::TransformPropagate),
+            )
             .insert_resource(SnakeMoveTimer(Timer::from_seconds(
                 1. / INITIAL_SPEED,
                 TimerMode::Repeating,
@@ -70,11 +110,11 @@ impl Plugin for SnakePlugin {
     }
 }
 
-fn get_corner_rotation(direction_in: Dir2, direction_out: Dir2) -> Quat {
-    let up = Dir2::Y;
-    let down = Dir2::NEG_Y;
-    let left = Dir2::NEG_X;
-    let right = Dir2::X;
+fn get_corner_rotation(direction_in: Direction, direction_out: Direction) -> Quat {
+    let up = Direction::Up;
+    let down = Direction::Down;
+    let left = Direction::Left;
+    let right = Direction::Right;
 
     let directions = (direction_in, direction_out);
 
@@ -99,67 +139,156 @@ fn get_rotation(direction: Vec2) -> Quat {
         } else {
             -FRAC_PI_2
         }
+    } else if direction.x > 0.0 {
+        0.0
     } else {
-        if direction.x > 0.0 {
-            0.0
-        } else {
-            PI
-        }
+        PI
     };
     Quat::from_rotation_z(rotation)
 }
 
 fn move_snake(
-    mut mesh_q: Query<&mut Mesh2dHandle>,
-    mut transform_q: Query<&mut Transform>,
     mut ev_move: EventReader<SnakeMoveEvent>,
-    mut body: ResMut<SnakeBody>,
-    corner_mesh: Res<CornerMesh>,
-    end_mesh: Res<EndMesh>,
-    mut current_direction: ResMut<CurrentDirection>,
-    is_dead: Res<IsDead>,
-    next_direction: Res<NextDirection>,
-    straight_mesh: Res<StraightMesh>,
+    mut ev_eat: EventWriter<EatEvent>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<AppState>>,
+    snake_q: Query<(&SnakeId, &NextDirection)>,
+    config: Res<GameConfig>,
 ) {
-    if is_dead.0 || ev_move.is_empty() {
+    if ev_move.is_empty() {
         return;
     }
     ev_move.clear();
 
-    // Make the old tail the new head
-    body.0.rotate_right(1);
+    let mut directions: Vec<_> = game.0.snakes().iter().map(|s| s.direction()).collect();
+    for (&SnakeId(i), next_direction) in &snake_q {
+        directions[i] = next_direction.0;
+    }
 
-    let new_head = body.0[0];
-    let old_head = body.0[1];
-    let new_tail = body.0[body.0.len() - 1];
-    let old_tail = body.0[body.0.len() - 2];
+    for outcome in game.0.step_all(&directions) {
+        if matches!(outcome, StepOutcome::Ate | StepOutcome::Won) {
+            ev_eat.send(EatEvent);
+        }
+    }
+    // Rivals don't carry on without anyone left to play against
+    let players_out = game.0.snakes()[..config.players]
+        .iter()
+        .all(|snake| !snake.is_alive());
+    if game.0.is_over() || players_out {
+        next_state.set(AppState::GameOver);
+    }
+}
 
-    *transform_q.get_mut(new_head).unwrap() = Transform {
-        translation: transform_q.get(old_head).unwrap().translation + next_direction.0.extend(0.),
-        rotation: get_rotation(*next_direction.0),
-        ..default()
-    };
+/// Works out which mesh segment `i` of the body needs and how it is rotated,
+/// based only on the cells of its neighbours.
+///
+/// Neighbouring cells are compared through the board's topology, so segments
+/// on either side of a wrapped edge still join up.
+fn segment_appearance(
+    cells: &[IVec2],
+    occupancy: &Occupancy,
+    direction: Direction,
+    i: usize,
+) -> (SegmentShape, Quat) {
+    let last = cells.len() - 1;
+    let step = |from: IVec2, to: IVec2| occupancy.direction_between(from, to).unwrap();
 
-    // Set the old head's mesh to either corner or straight
-    if current_direction.0 != next_direction.0 {
-        *mesh_q.get_mut(old_head).unwrap() = corner_mesh.0.clone().into();
-        transform_q.get_mut(old_head).unwrap().rotation =
-            get_corner_rotation(current_direction.0, next_direction.0);
+    if i == 0 {
+        let facing = if last == 0 {
+            direction
+        } else {
+            step(cells[1], cells[0])
+        };
+        return (SegmentShape::End, get_rotation(facing.as_ivec2().as_vec2()));
+    }
+    if i == last {
+        return (
+            SegmentShape::End,
+            get_rotation(step(cells[i - 1], cells[i]).as_ivec2().as_vec2()),
+        );
+    }
+
+    let direction_in = step(cells[i + 1], cells[i]);
+    let direction_out = step(cells[i], cells[i - 1]);
+
+    if direction_in == direction_out {
+        (
+            SegmentShape::Straight,
+            get_rotation(direction_in.as_ivec2().as_vec2()),
+        )
     } else {
-        *mesh_q.get_mut(old_head).unwrap() = straight_mesh.0.clone().into();
+        (
+            SegmentShape::Corner,
+            get_corner_rotation(direction_in, direction_out),
+        )
     }
-    transform_q.get_mut(new_tail).unwrap().rotation = {
-        let new_tail_pos = transform_q.get(new_tail).unwrap().translation;
-        let old_tail_pos = transform_q.get(old_tail).unwrap().translation;
-        let direction = new_tail_pos - old_tail_pos;
-        get_rotation(direction.truncate())
-    };
-    *mesh_q.get_mut(new_tail).unwrap() = end_mesh.0.clone().into();
+}
 
-    current_direction.0 = next_direction.0;
+fn render_snake(
+    mut cmd: Commands,
+    mut snake_q: Query<(&SnakeId, &SnakeMaterial, &mut SnakeSegments)>,
+    mut segment_q: Query<(
+        &mut GridPos,
+        &mut Transform,
+        &mut Mesh2dHandle,
+        &mut Handle<ColorMaterial>,
+    )>,
+    game: Res<Game>,
+    snake_meshes: Res<SnakeMeshes>,
+    dead_material: Res<DeadSnakeMaterial>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+
+    for (&SnakeId(id), alive_material, mut segments) in &mut snake_q {
+        let snake = &game.0.snakes()[id];
+        let cells: Vec<_> = snake.body().iter().copied().collect();
+        let material = if snake.is_alive() {
+            alive_material.0.clone()
+        } else {
+            dead_material.0.clone()
+        };
+
+        let excess = cells.len().min(segments.0.len());
+        for entity in segments.0.drain(excess..) {
+            cmd.entity(entity).despawn();
+        }
+
+        for (i, &cell) in cells.iter().enumerate() {
+            let (shape, rotation) =
+                segment_appearance(&cells, game.0.occupancy(), snake.direction(), i);
+            let mesh: Mesh2dHandle = match shape {
+                SegmentShape::Corner => snake_meshes.corner.clone(),
+                SegmentShape::End => snake_meshes.end.clone(),
+                SegmentShape::Straight => snake_meshes.straight.clone(),
+            }
+            .into();
+
+            if let Some(&entity) = segments.0.get(i) {
+                let (mut grid_pos, mut transform, mut segment_mesh, mut segment_material) =
+                    segment_q.get_mut(entity).unwrap();
+                grid_pos.set_if_neq(GridPos(cell));
+                transform.rotation = rotation;
+                *segment_mesh = mesh;
+                *segment_material = material.clone();
+            } else {
+                let entity = cmd
+                    .spawn(MaterialMesh2dBundle {
+                        mesh,
+                        material: material.clone(),
+                        transform: Transform::from_rotation(rotation),
+                        ..default()
+                    })
+                    .insert(GridPos(cell))
+                    .id();
+                segments.0.push(entity);
+            }
+        }
+    }
 }
 
-fn spawn_snake(
+fn setup_snake_assets(
     mut cmd: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
@@ -205,58 +334,100 @@ fn spawn_snake(
     .with_inserted_indices(Indices::U32(vec![0, 1, 2, 2, 1, 3]));
     let end_mesh_handle = meshes.add(end_mesh);
 
-    let material = materials.add(ColorMaterial::default());
+    let dead_material = materials.add(Color::from(DEAD_SNAKE_COLOR));
 
-    let transforms: Vec<_> = (0..SNAKE_LENGTH)
-        .map(|i| Transform::from_xyz(GRID_WIDTH / 2. - i as f32, GRID_HEIGHT / 2., 0.))
-        .collect();
+    cmd.insert_resource(SnakeMeshes {
+        corner: corner_mesh_handle,
+        end: end_mesh_handle,
+        straight: straight_mesh_handle,
+    });
+    cmd.insert_resource(DeadSnakeMaterial(dead_material));
+}
 
-    let mut body = VecDeque::new();
+/// Starts a fresh game, clearing away the snakes from any previous one.
+fn reset_snake(
+    mut cmd: Commands,
+    mut timer: ResMut<SnakeMoveTimer>,
+    mut is_paused: ResMut<IsPaused>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    snake_q: Query<(Entity, &SnakeSegments)>,
+    config: Res<GameConfig>,
+) {
+    for (entity, segments) in &snake_q {
+        for &segment in &segments.0 {
+            cmd.entity(segment).despawn();
+        }
+        cmd.entity(entity).despawn();
+    }
 
-    for (i, transform) in transforms.into_iter().enumerate() {
-        let mesh = if i == 0 {
-            end_mesh_handle.clone().into()
-        } else if i == SNAKE_LENGTH - 1 {
-            end_mesh_handle.clone().into()
-        } else {
-            straight_mesh_handle.clone().into()
+    let game = GameState::with_snakes(
+        config.grid_width,
+        config.grid_height,
+        config.wall_mode,
+        config.snake_length,
+        config.snakes(),
+        config.seed.unwrap_or_else(rand::random),
+    );
+    for (id, snake) in game.snakes().iter().enumerate() {
+        let mut entity = cmd.spawn((
+            SnakeId(id),
+            NextDirection(snake.direction()),
+            SnakeSegments::default(),
+            SnakeMaterial(materials.add(Color::from(snake_color(&config, id)))),
+        ));
+        match id
+            .checked_sub(config.players)
+            .and_then(|i| config.rivals.get(i))
+        {
+            Some(rival) => entity.insert(Rival(RivalController::new(
+                rival.difficulty,
+                // Each rival makes its own mistakes, the same ones every
+                // time the same seed is played
+                game.seed().wrapping_add(id as u64),
+            ))),
+            None => entity.insert((InputQueue::default(), Player(id))),
         };
-        let rotation = if i == SNAKE_LENGTH - 1 {
-            Quat::from_rotation_z(PI)
-        } else {
-            Quat::default()
-        };
-        body.push_back(
-            cmd.spawn(MaterialMesh2dBundle {
-                mesh,
-                material: material.clone(),
-                transform: Transform {
-                    translation: transform.translation,
-                    rotation,
-                    ..default()
-                },
-                ..default()
-            })
-            .id(),
-        );
     }
-    cmd.insert_resource(CurrentDirection(Dir2::X));
-    cmd.insert_resource(NextDirection(Dir2::X));
-    cmd.insert_resource(SnakeBody(body));
-    cmd.insert_resource(StraightMesh(straight_mesh_handle));
-    cmd.insert_resource(CornerMesh(corner_mesh_handle));
-    cmd.insert_resource(EndMesh(end_mesh_handle));
-    cmd.insert_resource(SnakeMaterial(material));
+    cmd.insert_resource(Game(game));
+    timer.0 = Timer::from_seconds(1. / config.speed(0), TimerMode::Repeating);
+    // A game left while paused doesn't leave the next one paused
+    is_paused.0 = false;
+}
+
+/// Colour of snake `id`: the players' snakes come first, then the rivals'.
+pub fn snake_color(config: &GameConfig, id: usize) -> Srgba {
+    match id
+        .checked_sub(config.players)
+        .and_then(|i| config.rivals.get(i))
+    {
+        Some(rival) => rival.color,
+        None => PLAYER_COLORS[id % PLAYER_COLORS.len()],
+    }
+}
+
+/// Name of snake `id` on the game over screen, such as "Player 1" or
+/// "Rival 2".
+pub fn snake_name(config: &GameConfig, id: usize) -> String {
+    if id < config.players {
+        format!("Player {}", id + 1)
+    } else {
+        format!("Rival {}", id - config.players + 1)
+    }
+}
+
+fn sync_grid_transforms(mut q: Query<(&GridPos, &mut Transform), Changed<GridPos>>) {
+    for (grid_pos, mut transform) in &mut q {
+        transform.translation = grid_pos.0.as_vec2().extend(transform.translation.z);
+    }
 }
 
 fn tick_move_timer(
     mut timer: ResMut<SnakeMoveTimer>,
     mut ev_move: EventWriter<SnakeMoveEvent>,
-    is_dead: Res<IsDead>,
     is_paused: Res<IsPaused>,
     time: Res<Time>,
 ) {
-    if !is_paused.0 && !is_dead.0 && timer.0.tick(time.delta()).just_finished() {
+    if !is_paused.0 && timer.0.tick(time.delta()).just_finished() {
         ev_move.send(SnakeMoveEvent);
     }
 }
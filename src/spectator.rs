@@ -0,0 +1,382 @@
+//! Watching games played somewhere else.
+//!
+//! The `server` binary plays games without a window and sends every
+//! spectator connected to it over TCP a line of JSON per message: its
+//! settings once, as soon as the spectator connects, and then a snapshot of
+//! the board after every move. `snake --watch` is such a spectator, drawing
+//! each snapshot as it arrives with nothing to steer.
+
+use crate::{
+    config::GameConfig,
+    food::Food,
+    game::{GameState, Snake},
+    snake::{Game, GridPos, SnakeMoveSet, SnakeMoveTimer, SnakeMoveTimerTickSet},
+    state::AppState,
+};
+use bevy::{prelude::*, utils::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+    thread,
+};
+
+/// How long to wait for the server to say hello before giving up on it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct SpectatorPlugin;
+
+/// A line the server sends its spectators.
+#[derive(Serialize, Deserialize)]
+pub enum Broadcast {
+    /// The settings every game on the server is played with. Sent once, as
+    /// soon as a spectator connects.
+    Config(GameConfig),
+    /// The board after a move. Sent after every move, and at the start of
+    /// every game.
+    Snapshot(Snapshot),
+}
+
+/// Everything on the board at one point in a game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Games the server finished before this one.
+    pub game: u64,
+    /// Moves made so far in this game.
+    pub moves: u64,
+    pub snakes: Vec<Snake>,
+    pub food: Option<IVec2>,
+}
+
+/// Connection to the server a game is being watched from.
+#[derive(Resource)]
+pub struct Spectator {
+    address: String,
+    snapshots: Mutex<Receiver<Snapshot>>,
+    /// The game and move the last snapshot shown was taken at.
+    shown: Option<(u64, u64)>,
+    connected: bool,
+}
+
+#[derive(Component)]
+struct SpectatorText;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            setup_spectator_ui.run_if(resource_exists::<Spectator>),
+        )
+        .add_systems(
+            Update,
+            (
+                // The server does the moving, so the game here never does
+                hold_still.before(SnakeMoveTimerTickSet),
+                show_snapshots.before(SnakeMoveSet),
+                update_spectator_ui,
+            )
+                .chain()
+                .run_if(resource_exists::<Spectator>)
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+impl Snapshot {
+    /// Snapshot of `state` after `moves` moves of game number `game`.
+    pub fn new(game: u64, moves: u64, state: &GameState) -> Snapshot {
+        Snapshot {
+            game,
+            moves,
+            snakes: state.snakes().to_vec(),
+            food: state.food(),
+        }
+    }
+
+    /// The game as it stood, to draw, or `None` if it doesn't fit the board
+    /// `config` describes. Snakes have to lie on the board in one piece, each
+    /// cell next to the one before it, for their segments to be drawn.
+    fn to_game(&self, config: &GameConfig) -> Option<GameState> {
+        if self.snakes.len() != config.snakes()
+            || self.snakes.iter().any(|snake| snake.body().is_empty())
+        {
+            return None;
+        }
+        let state = GameState::with_board(
+            config.grid_width,
+            config.grid_height,
+            config.wall_mode,
+            self.snakes.clone(),
+            self.food,
+            0,
+        );
+
+        let occupancy = state.occupancy();
+        let in_one_piece = |body: &VecDeque<IVec2>| {
+            body.iter().all(|&cell| occupancy.in_bounds(cell))
+                && body
+                    .iter()
+                    .zip(body.iter().skip(1))
+                    .all(|(&a, &b)| occupancy.direction_between(a, b).is_some())
+        };
+        let food_on_board = self.food.is_none_or(|food| occupancy.in_bounds(food));
+        (food_on_board && self.snakes.iter().all(|snake| in_one_piece(snake.body())))
+            .then_some(state)
+    }
+}
+
+impl Spectator {
+    /// Connects to the server at `address` and waits for its settings.
+    pub fn connect(address: &str) -> Result<(Spectator, GameConfig), String> {
+        let socket_address = address
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("no address found for `{address}`"))?;
+        let stream = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)
+            .map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(CONNECT_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+
+        let mut reader = BufReader::new(stream);
+        let mut hello = String::new();
+        match reader.read_line(&mut hello) {
+            Ok(0) => return Err("the server closed the connection".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        let Ok(Broadcast::Config(config)) = serde_json::from_str(&hello) else {
+            return Err("the server didn't send its settings".to_string());
+        };
+
+        // From here on snapshots arrive whenever the server makes a move,
+        // however long that takes
+        reader
+            .get_ref()
+            .set_read_timeout(None)
+            .map_err(|e| e.to_string())?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match serde_json::from_str(&line) {
+                    Ok(Broadcast::Snapshot(snapshot)) => {
+                        if sender.send(snapshot).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Broadcast::Config(_)) => {}
+                    Err(e) => warn!("Ignoring a line from the server that couldn't be read: {e}"),
+                }
+            }
+        });
+
+        let spectator = Spectator {
+            address: address.to_string(),
+            snapshots: Mutex::new(receiver),
+            shown: None,
+            connected: true,
+        };
+        Ok((spectator, config))
+    }
+}
+
+fn hold_still(mut timer: ResMut<SnakeMoveTimer>) {
+    timer.0.pause();
+}
+
+/// Puts the latest snapshot from the server on the board.
+fn show_snapshots(
+    mut spectator: ResMut<Spectator>,
+    mut game: ResMut<Game>,
+    mut food_q: Query<(&mut GridPos, &mut Visibility), With<Food>>,
+    config: Res<GameConfig>,
+) {
+    let mut latest = None;
+    loop {
+        match spectator.snapshots.get_mut().unwrap().try_recv() {
+            Ok(snapshot) => latest = Some(snapshot),
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                if spectator.connected {
+                    warn!("Lost the connection to {}", spectator.address);
+                    spectator.connected = false;
+                }
+                break;
+            }
+        }
+    }
+    let Some(snapshot) = latest else {
+        return;
+    };
+    let Some(state) = snapshot.to_game(&config) else {
+        warn!("Ignoring a snapshot that doesn't fit the board");
+        return;
+    };
+
+    game.0 = state;
+    spectator.shown = Some((snapshot.game, snapshot.moves));
+    if let Ok((mut grid_pos, mut visibility)) = food_q.get_single_mut() {
+        match game.0.food() {
+            Some(food) => {
+                grid_pos.set_if_neq(GridPos(food));
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn setup_spectator_ui(mut cmd: Commands, spectator: Res<Spectator>) {
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.),
+            top: Val::Px(10.),
+            padding: UiRect::all(Val::Px(5.)),
+            ..default()
+        },
+        background_color: Srgba::BLACK.with_alpha(0.8).into(),
+        border_radius: BorderRadius::all(Val::Px(10.)),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new(
+                    format!("Watching {}", spectator.address),
+                    TextStyle {
+                        font_size: 30.,
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font_size: 20.,
+                        color: Srgba::gray(0.7).into(),
+                        ..default()
+                    },
+                ),
+            ]))
+            .insert(SpectatorText);
+    });
+}
+
+fn update_spectator_ui(
+    spectator: Res<Spectator>,
+    mut query: Query<&mut Text, With<SpectatorText>>,
+) {
+    if !spectator.is_changed() {
+        return;
+    }
+
+    let mut text = query.single_mut();
+    text.sections[1].value = match spectator.shown {
+        _ if !spectator.connected => "\nLost the connection to the server".to_string(),
+        Some((game, moves)) => format!("\nGame {}, move {moves}", game + 1),
+        None => "\nWaiting for the next move".to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::WallMode;
+    use serde_json::{json, Value};
+
+    fn config() -> GameConfig {
+        let mut config = GameConfig {
+            grid_width: 8,
+            grid_height: 6,
+            ..GameConfig::default()
+        };
+        config.sanitize();
+        config
+    }
+
+    /// A snapshot of a fresh game on the board from `config`, as JSON to be
+    /// tampered with.
+    fn snapshot(config: &GameConfig) -> Value {
+        let state = GameState::with_snakes(
+            config.grid_width,
+            config.grid_height,
+            config.wall_mode,
+            config.snake_length,
+            config.snakes(),
+            0,
+        );
+        serde_json::to_value(Snapshot::new(0, 0, &state)).unwrap()
+    }
+
+    /// Breaks a snapshot in one particular way.
+    type Tamper = fn(&mut Value);
+
+    fn to_game(snapshot: Value, config: &GameConfig) -> Option<GameState> {
+        serde_json::from_value::<Snapshot>(snapshot)
+            .unwrap()
+            .to_game(config)
+    }
+
+    #[test]
+    fn accepts_a_snapshot_that_fits() {
+        let config = config();
+        let game = to_game(snapshot(&config), &config).unwrap();
+        assert_eq!(game.body().len(), config.snake_length);
+        assert!(game.food().is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_snapshots() {
+        let config = config();
+        let tampered: [(&str, Tamper); 5] = [
+            ("cell off the board", |s| {
+                s["snakes"][0]["body"][0] = json!([8, 2])
+            }),
+            ("negative cell", |s| {
+                s["snakes"][0]["body"][1] = json!([-1, 2])
+            }),
+            ("gap in the body", |s| {
+                s["snakes"][0]["body"][1] = json!([0, 0])
+            }),
+            ("repeated cell", |s| {
+                s["snakes"][0]["body"][1] = s["snakes"][0]["body"][0].clone()
+            }),
+            ("food off the board", |s| s["food"] = json!([3, 40])),
+        ];
+        for (what, tamper) in tampered {
+            let mut snapshot = snapshot(&config);
+            tamper(&mut snapshot);
+            assert!(to_game(snapshot, &config).is_none(), "{what}");
+        }
+
+        let mut empty = snapshot(&config);
+        empty["snakes"][0]["body"] = json!([]);
+        assert!(to_game(empty, &config).is_none());
+
+        let mut extra = snapshot(&config);
+        let snake = extra["snakes"][0].clone();
+        extra["snakes"].as_array_mut().unwrap().push(snake);
+        assert!(to_game(extra, &config).is_none());
+    }
+
+    #[test]
+    fn allows_the_jump_across_wrapped_edges() {
+        let mut config = config();
+        config.wall_mode = WallMode::Wrap;
+        let mut snapshot = snapshot(&config);
+        snapshot["snakes"][0]["body"] = json!([[0, 2], [7, 2], [6, 2]]);
+        assert!(to_game(snapshot.clone(), &config).is_some());
+
+        config.wall_mode = WallMode::Solid;
+        assert!(to_game(snapshot, &config).is_none());
+    }
+}
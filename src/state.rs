@@ -0,0 +1,14 @@
+use bevy::prelude::*;
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    /// Waiting for the other player to join a network game.
+    Lobby,
+    Settings,
+    Controls,
+    HighScores,
+    Playing,
+    GameOver,
+}